@@ -0,0 +1,229 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::casefold::{self, Fold};
+
+// Aho–Corasick automaton for finding any of a set of literal patterns in a
+// single pass over the line, however many patterns there are.
+//
+// The patterns go into a trie of chars. Every node also gets a failure link to
+// the node for the longest proper suffix of its path that is also in the trie,
+// so the scan never has to back up after a mismatch.
+// Case insensitive matching builds the trie from folded patterns and folds the
+// line one character at a time while scanning.
+
+#[derive(Debug, Clone, Default)]
+struct Node {
+    next: HashMap<char, usize>,
+    fail: usize,
+    // patterns that end at this node, directly or through failure links
+    outputs: Vec<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AhoCorasick {
+    nodes: Vec<Node>,
+    // length of each pattern in (folded) chars
+    lengths: Vec<usize>,
+    max_length: usize,
+    has_empty: bool,
+    // Some(turkic) for case insensitive matching
+    fold: Option<bool>,
+}
+
+const ROOT: usize = 0;
+
+impl AhoCorasick {
+    pub fn new<S: AsRef<str>>(patterns: &[S], fold: Option<bool>) -> AhoCorasick {
+        let mut ac = AhoCorasick {
+            nodes: vec![Node::default()],
+            lengths: Vec::new(),
+            max_length: 0,
+            has_empty: false,
+            fold,
+        };
+        for (index, pattern) in patterns.iter().enumerate() {
+            let chars: Vec<char> = match fold {
+                Some(turkic) => pattern.as_ref().chars().flat_map(|c| casefold::fold(c, turkic)).collect(),
+                None => pattern.as_ref().chars().collect(),
+            };
+            ac.insert(index, &chars);
+        }
+        ac.build_failure_links();
+        ac
+    }
+
+    fn insert(&mut self, index: usize, chars: &[char]) {
+        let mut node = ROOT;
+        for &c in chars {
+            node = match self.nodes[node].next.get(&c) {
+                Some(&next) => next,
+                None => {
+                    self.nodes.push(Node::default());
+                    let next = self.nodes.len() - 1;
+                    self.nodes[node].next.insert(c, next);
+                    next
+                }
+            };
+        }
+        self.nodes[node].outputs.push(index);
+        self.lengths.push(chars.len());
+        self.max_length = self.max_length.max(chars.len());
+        self.has_empty |= chars.is_empty();
+    }
+
+    // Breadth first, so a node's failure target is always finished before the node
+    fn build_failure_links(&mut self) {
+        let mut queue: VecDeque<usize> = self.nodes[ROOT].next.values().copied().collect();
+        while let Some(node) = queue.pop_front() {
+            let edges: Vec<(char, usize)> = self.nodes[node].next.iter().map(|(&c, &n)| (c, n)).collect();
+            for (c, child) in edges {
+                let mut fail = self.nodes[node].fail;
+                let target = loop {
+                    if let Some(&next) = self.nodes[fail].next.get(&c) {
+                        break next;
+                    }
+                    if fail == ROOT {
+                        break ROOT;
+                    }
+                    fail = self.nodes[fail].fail;
+                };
+                self.nodes[child].fail = target;
+                let inherited = self.nodes[target].outputs.clone();
+                self.nodes[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+    }
+
+    fn step(&self, mut node: usize, c: char) -> usize {
+        loop {
+            if let Some(&next) = self.nodes[node].next.get(&c) {
+                return next;
+            }
+            if node == ROOT {
+                return ROOT;
+            }
+            node = self.nodes[node].fail;
+        }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    // Byte span of the leftmost hit starting at or after `start`, preferring
+    // the longest pattern when several start at the same place.
+    // Like CaseFolder, hits always cover whole characters of the line.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        self.find_at_where(line, start, &|_, _| true)
+    }
+
+    // Like find_at, but only hits whose span `accept` agrees with count, so a
+    // shorter pattern can still win when the longest one at a place is rejected
+    pub fn find_at_where(
+        &self,
+        line: &str,
+        start: usize,
+        accept: &dyn Fn(usize, usize) -> bool,
+    ) -> Option<(usize, usize)> {
+        // the empty pattern is a hit at every position
+        let empty_at = |pos: usize| self.has_empty && accept(pos, pos);
+        if empty_at(start) {
+            return Some((start, start));
+        }
+        let mut node = ROOT;
+        // for every folded char scanned so far: the byte offset of the line
+        // char it came from, and whether it's the first char of that char's folding
+        let mut origins: Vec<(usize, bool)> = Vec::new();
+        // best hit so far, with the index into origins where it starts
+        let mut best: Option<(usize, usize, usize)> = None;
+
+        for (i, c) in line[start..].char_indices() {
+            let pos = start + i;
+            let end = pos + c.len_utf8();
+            let folded = match self.fold {
+                Some(turkic) => casefold::fold(c, turkic),
+                None => Fold::one(c),
+            };
+            let count = folded.len();
+            for (k, f) in folded.enumerate() {
+                origins.push((pos, k == 0));
+                node = self.step(node, f);
+                // a hit can't end in the middle of a character's folding
+                if k + 1 < count {
+                    continue;
+                }
+                for &pattern in &self.nodes[node].outputs {
+                    let first = origins.len() - self.lengths[pattern];
+                    let (hit_start, aligned) = origins[first];
+                    let better = match best {
+                        None => true,
+                        Some((s, e, _)) => hit_start < s || (hit_start == s && end > e),
+                    };
+                    if aligned && better && accept(hit_start, end) {
+                        best = Some((hit_start, end, first));
+                    }
+                }
+            }
+            // anything starting before the best hit would have ended by now
+            if let Some((_, _, first)) = best {
+                if origins.len() >= first + self.max_length {
+                    break;
+                }
+            }
+        }
+        best.map(|(s, e, _)| (s, e)).or_else(|| {
+            line[start..]
+                .char_indices()
+                .map(|(i, c)| start + i + c.len_utf8())
+                .find(|&pos| empty_at(pos))
+                .map(|pos| (pos, pos))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_any_pattern() {
+        let ac = AhoCorasick::new(&["he", "she", "his", "hers"], None);
+        assert_eq!(Some((1, 4)), ac.find_at("ushers", 0));
+        // "his" starts before position 2, so it doesn't count
+        assert_eq!(Some((3, 6)), ac.find_at("ahishers", 2));
+        assert!(!ac.is_match("nothing to see"));
+    }
+
+    #[test]
+    fn leftmost_longest() {
+        let ac = AhoCorasick::new(&["abcd", "bc", "abc"], None);
+        assert_eq!(Some((1, 5)), ac.find_at("xabcdx", 0));
+        let ac = AhoCorasick::new(&["b", "abcde"], None);
+        // abcde starts first, even though b is found first
+        assert_eq!(Some((0, 5)), ac.find_at("abcde", 0));
+    }
+
+    #[test]
+    fn rejected_hits() {
+        let ac = AhoCorasick::new(&["ab", "ab-c"], None);
+        // the longer ab-c is rejected, so fall back to ab
+        assert_eq!(Some((0, 2)), ac.find_at_where("ab-cd", 0, &|_, e| e != 4));
+        assert_eq!(None, ac.find_at_where("ab-cd", 0, &|_, _| false));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let ac = AhoCorasick::new(&["STRASSE", "rust"], Some(false));
+        assert_eq!(Some((0, 7)), ac.find_at("Straße", 0));
+        assert_eq!(Some((1, 5)), ac.find_at("TRUST", 0));
+    }
+
+    #[test]
+    fn empty_pattern_set() {
+        let ac = AhoCorasick::new::<&str>(&[], None);
+        assert!(!ac.is_match("anything"));
+        let ac = AhoCorasick::new(&["x", ""], None);
+        assert!(ac.is_match("anything"));
+    }
+}
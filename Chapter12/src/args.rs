@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fmt;
+
+// Splits command line arguments into flags and positional values.
+//   -abc          is three short flags a, b and c
+//   --name=value  and  --name value  are both a long flag with a value
+//   --            ends flag parsing, everything after it is positional
+//   -             on its own is positional
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgError {
+    UnknownFlag(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+    InvalidValue { flag: String, value: String },
+    // a flag that only makes sense together with another one
+    Requires { flag: String, needs: String },
+    // two flags that can't be used together
+    Conflicts { flag: String, with: String },
+    // a pattern file given with -f couldn't be read
+    PatternFile { path: String, reason: String },
+    // the .minigreprc file couldn't be read or has a bad setting in it
+    ConfigFile { path: String, reason: String },
+    MissingQuery,
+    MissingFilePath,
+}
+
+impl fmt::Display for ArgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ArgError::UnknownFlag(flag) => write!(f, "unknown flag '{flag}'"),
+            ArgError::MissingValue(flag) => write!(f, "flag '{flag}' needs a value"),
+            ArgError::UnexpectedValue(flag) => write!(f, "flag '{flag}' doesn't take a value"),
+            ArgError::InvalidValue { flag, value } => write!(f, "invalid value '{value}' for flag '{flag}'"),
+            ArgError::Requires { flag, needs } => write!(f, "flag '{flag}' needs '{needs}'"),
+            ArgError::Conflicts { flag, with } => write!(f, "flag '{flag}' can't be used with '{with}'"),
+            ArgError::PatternFile { path, reason } => write!(f, "can't read patterns from '{path}': {reason}"),
+            ArgError::ConfigFile { path, reason } => write!(f, "error in config file '{path}': {reason}"),
+            ArgError::MissingQuery => write!(f, "Did not get query string"),
+            ArgError::MissingFilePath => write!(f, "Did not get file path string"),
+        }
+    }
+}
+
+impl Error for ArgError {}
+
+#[derive(Debug, PartialEq)]
+pub enum Arg {
+    Short(char),
+    Long(String),
+    Positional(String),
+}
+
+impl Arg {
+    // The flag as the user typed it, for error messages
+    pub fn display(&self) -> String {
+        match self {
+            Arg::Short(c) => format!("-{c}"),
+            Arg::Long(name) => format!("--{name}"),
+            Arg::Positional(value) => value.clone(),
+        }
+    }
+}
+
+pub struct ArgParser<I: Iterator<Item = String>> {
+    args: I,
+    // remaining characters of a cluster like `-inv`
+    shorts: Vec<char>,
+    // value attached with `=` to the last long flag
+    attached: Option<String>,
+    // set once `--` has been seen
+    positional_only: bool,
+}
+
+impl<I: Iterator<Item = String>> ArgParser<I> {
+    pub fn new(args: I) -> ArgParser<I> {
+        ArgParser {
+            args,
+            shorts: Vec::new(),
+            attached: None,
+            positional_only: false,
+        }
+    }
+
+    pub fn next_arg(&mut self) -> Option<Arg> {
+        if !self.shorts.is_empty() {
+            return Some(Arg::Short(self.shorts.remove(0)));
+        }
+        let arg = self.args.next()?;
+        if self.positional_only || arg == "-" || !arg.starts_with('-') {
+            return Some(Arg::Positional(arg));
+        }
+        if arg == "--" {
+            self.positional_only = true;
+            return self.next_arg();
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            return Some(match long.split_once('=') {
+                Some((name, value)) => {
+                    self.attached = Some(value.to_string());
+                    Arg::Long(name.to_string())
+                }
+                None => Arg::Long(long.to_string()),
+            });
+        }
+        self.shorts = arg.chars().skip(1).collect();
+        Some(Arg::Short(self.shorts.remove(0)))
+    }
+
+    // The value of a flag: `--name=value`, `-Avalue`, or the next argument
+    pub fn value(&mut self, flag: &Arg) -> Result<String, ArgError> {
+        if let Some(value) = self.attached.take() {
+            return Ok(value);
+        }
+        if !self.shorts.is_empty() {
+            return Ok(self.shorts.drain(..).collect());
+        }
+        self.args.next().ok_or_else(|| ArgError::MissingValue(flag.display()))
+    }
+
+    // For flags whose value is optional: only `--name=value` counts as a value
+    pub fn optional_value(&mut self) -> Option<String> {
+        self.attached.take()
+    }
+
+    // Must be called for flags that don't take a value, so that
+    // `--ignore-case=yes` is reported instead of silently accepted
+    pub fn no_value(&mut self, flag: &Arg) -> Result<(), ArgError> {
+        match self.attached.take() {
+            Some(_) => Err(ArgError::UnexpectedValue(flag.display())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(args: &[&str]) -> ArgParser<std::vec::IntoIter<String>> {
+        let args: Vec<String> = args.iter().map(|a| a.to_string()).collect();
+        ArgParser::new(args.into_iter())
+    }
+
+    #[test]
+    fn splits_flags_and_positionals() {
+        let mut p = parser(&["-in", "--count", "query", "-", "--", "-v"]);
+        assert_eq!(Some(Arg::Short('i')), p.next_arg());
+        assert_eq!(Some(Arg::Short('n')), p.next_arg());
+        assert_eq!(Some(Arg::Long("count".to_string())), p.next_arg());
+        assert_eq!(Some(Arg::Positional("query".to_string())), p.next_arg());
+        assert_eq!(Some(Arg::Positional("-".to_string())), p.next_arg());
+        assert_eq!(Some(Arg::Positional("-v".to_string())), p.next_arg());
+        assert_eq!(None, p.next_arg());
+    }
+
+    #[test]
+    fn flag_values() {
+        let mut p = parser(&["--include=*.rs", "--exclude", "target", "-A3", "-B"]);
+        let flag = p.next_arg().unwrap();
+        assert_eq!("*.rs", p.value(&flag).unwrap());
+        let flag = p.next_arg().unwrap();
+        assert_eq!("target", p.value(&flag).unwrap());
+        let flag = p.next_arg().unwrap();
+        assert_eq!("3", p.value(&flag).unwrap());
+        let flag = p.next_arg().unwrap();
+        assert_eq!(Err(ArgError::MissingValue("-B".to_string())), p.value(&flag));
+    }
+
+    #[test]
+    fn unexpected_value() {
+        let mut p = parser(&["--count=yes"]);
+        let flag = p.next_arg().unwrap();
+        assert_eq!(Err(ArgError::UnexpectedValue("--count".to_string())), p.no_value(&flag));
+    }
+}
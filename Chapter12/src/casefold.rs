@@ -0,0 +1,247 @@
+// Unicode case folding for case insensitive search.
+//
+// Folding maps every character to a caseless form so that two strings match
+// ignoring case when their folded forms are equal. Unlike to_lowercase it
+// handles characters that fold to more than one character (ß -> ss, ﬁ -> fi)
+// and the extra lowercase forms of some letters (ς and σ, ſ and s).
+// Turkish and Azeri fold I to dotless ı and İ to i instead of the default.
+
+// The folded form of one character, at most three characters long.
+// Being an iterator over an inline array means folding never allocates.
+#[derive(Debug, Clone)]
+pub struct Fold {
+    chars: [char; 3],
+    len: usize,
+    pos: usize,
+}
+
+impl Fold {
+    pub fn one(c: char) -> Fold {
+        Fold {
+            chars: [c, '\0', '\0'],
+            len: 1,
+            pos: 0,
+        }
+    }
+
+    fn from_chars(chars: impl Iterator<Item = char>) -> Fold {
+        let mut fold = Fold {
+            chars: ['\0'; 3],
+            len: 0,
+            pos: 0,
+        };
+        for c in chars.take(3) {
+            fold.chars[fold.len] = c;
+            fold.len += 1;
+        }
+        fold
+    }
+}
+
+impl Iterator for Fold {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        if self.pos < self.len {
+            self.pos += 1;
+            Some(self.chars[self.pos - 1])
+        } else {
+            None
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.len - self.pos, Some(self.len - self.pos))
+    }
+}
+
+impl ExactSizeIterator for Fold {}
+
+pub fn fold(c: char, turkic: bool) -> Fold {
+    if turkic {
+        match c {
+            'I' => return Fold::one('ı'),
+            'İ' => return Fold::one('i'),
+            _ => {}
+        }
+    }
+    if c.is_ascii() {
+        return Fold::one(c.to_ascii_lowercase());
+    }
+    if let Some(folded) = special_fold(c) {
+        return folded;
+    }
+    let mut lower = c.to_lowercase();
+    if lower.len() > 1 {
+        return Fold::from_chars(lower);
+    }
+    let lower = lower.next().unwrap_or(c);
+    // some uppercase letters lowercase to a letter that folds further, like ẞ -> ß -> ss
+    special_fold(lower).unwrap_or(Fold::one(lower))
+}
+
+// Foldings that differ from to_lowercase
+fn special_fold(c: char) -> Option<Fold> {
+    let s = match c {
+        // multi-character foldings
+        'ß' | 'ẞ' => "ss",
+        'İ' => "i\u{307}",
+        'ŉ' => "\u{2bc}n",
+        'ǰ' => "j\u{30c}",
+        'ΐ' => "\u{3b9}\u{308}\u{301}",
+        'ΰ' => "\u{3c5}\u{308}\u{301}",
+        'և' => "եւ",
+        'ẖ' => "h\u{331}",
+        'ẗ' => "t\u{308}",
+        'ẘ' => "w\u{30a}",
+        'ẙ' => "y\u{30a}",
+        'ẚ' => "a\u{2be}",
+        'ﬀ' => "ff",
+        'ﬁ' => "fi",
+        'ﬂ' => "fl",
+        'ﬃ' => "ffi",
+        'ﬄ' => "ffl",
+        'ﬅ' | 'ﬆ' => "st",
+        'ﬓ' => "մն",
+        'ﬔ' => "մե",
+        'ﬕ' => "մի",
+        'ﬖ' => "վն",
+        'ﬗ' => "մխ",
+        'ᾳ' | 'ᾼ' => "αι",
+        'ῃ' | 'ῌ' => "ηι",
+        'ῳ' | 'ῼ' => "ωι",
+        // lowercase letters with a different caseless form
+        'ς' => "σ",
+        'ſ' => "s",
+        'ϐ' => "β",
+        'ϑ' => "θ",
+        'ϕ' => "φ",
+        'ϖ' => "π",
+        'ϰ' => "κ",
+        'ϱ' => "ρ",
+        'ϵ' => "ε",
+        'ẛ' => "ṡ",
+        '\u{345}' | '\u{1fbe}' => "ι",
+        '\u{1c80}' => "в",
+        '\u{1c81}' => "д",
+        '\u{1c82}' => "о",
+        '\u{1c83}' => "с",
+        '\u{1c84}' | '\u{1c85}' => "т",
+        '\u{1c86}' => "ъ",
+        '\u{1c87}' => "ѣ",
+        '\u{1c88}' => "ꙋ",
+        _ => return special_range_fold(c),
+    };
+    Some(Fold::from_chars(s.chars()))
+}
+
+// Blocks of foldings that follow a pattern
+fn special_range_fold(c: char) -> Option<Fold> {
+    let code = c as u32;
+    let shifted = |from: u32, to: u32| char::from_u32(code - from + to);
+    match code {
+        // Greek with ypogegrammeni: ᾀ -> ἀι, and the title case forms ᾈ -> ἀι
+        0x1f80..=0x1faf => {
+            let base = [0x1f00, 0x1f20, 0x1f60][((code - 0x1f80) / 0x10) as usize];
+            let letter = char::from_u32(base + (code & 0x7))?;
+            Some(Fold::from_chars([letter, 'ι'].into_iter()))
+        }
+        // Cherokee folds to the uppercase letters
+        0xab70..=0xabbf => shifted(0xab70, 0x13a0).map(Fold::one),
+        0x13f8..=0x13fd => shifted(0x13f8, 0x13f0).map(Fold::one),
+        _ => None,
+    }
+}
+
+// Case insensitive substring search that folds the line as it goes,
+// so nothing is allocated per line
+#[derive(Debug, Clone)]
+pub struct CaseFolder {
+    // the folded query
+    query: Vec<char>,
+    turkic: bool,
+}
+
+impl CaseFolder {
+    pub fn new(query: &str, turkic: bool) -> CaseFolder {
+        CaseFolder {
+            query: query.chars().flat_map(|c| fold(c, turkic)).collect(),
+            turkic,
+        }
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+
+    // Byte span of the first hit starting at or after `start`.
+    // Hits always cover whole characters of the line: "ss" is found in "ß",
+    // but "s" isn't since it would only cover half of it.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        line[start..]
+            .char_indices()
+            .map(|(i, _)| start + i)
+            .chain(std::iter::once(line.len()))
+            .find_map(|pos| self.match_at(line, pos).map(|end| (pos, end)))
+    }
+
+    // End of the hit starting at pos, if there is one
+    fn match_at(&self, line: &str, pos: usize) -> Option<usize> {
+        if self.query.is_empty() {
+            return Some(pos);
+        }
+        let mut matched = 0;
+        let mut end = pos;
+        for c in line[pos..].chars() {
+            for folded in fold(c, self.turkic) {
+                if self.query.get(matched) != Some(&folded) {
+                    return None;
+                }
+                matched += 1;
+            }
+            end += c.len_utf8();
+            if matched == self.query.len() {
+                return Some(end);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn folded(s: &str, turkic: bool) -> String {
+        s.chars().flat_map(|c| fold(c, turkic)).collect()
+    }
+
+    #[test]
+    fn full_folding() {
+        assert_eq!("strasse", folded("Straße", false));
+        assert_eq!("strasse", folded("STRAẞE", false));
+        assert_eq!("office", folded("O\u{fb03}CE", false));
+        assert_eq!("σίσυφοσ", folded("ΣΊΣΥΦΟΣ", false));
+        assert_eq!("σίσυφοσ", folded("σίσυφος", false));
+        assert_eq!("ἀι", folded("ᾈ", false));
+        // Devanagari has no case, so it's left alone
+        assert_eq!("नमस्ते", folded("नमस्ते", false));
+    }
+
+    #[test]
+    fn turkic_folding() {
+        assert_eq!("i\u{307}stanbul", folded("İstanbul", false));
+        assert_eq!("istanbul", folded("İstanbul", true));
+        assert_eq!("ıspanak", folded("ISPANAK", true));
+        assert_eq!("ıspanak", folded("ıspanak", true));
+    }
+
+    #[test]
+    fn spans_cover_whole_characters() {
+        let folder = CaseFolder::new("SS", false);
+        assert_eq!(Some((4, 6)), folder.find_at("Straße", 0));
+        assert_eq!(None, CaseFolder::new("s", false).find_at("ß", 0));
+        // the ligature ﬁ is 3 bytes long
+        assert_eq!(Some((2, 5)), CaseFolder::new("FI", false).find_at("of\u{fb01}ce", 0));
+    }
+}
@@ -0,0 +1,144 @@
+use std::error::Error;
+use std::fmt;
+
+// --color=auto|always|never
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    // color only when stdout is a terminal
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorChoiceError(pub String);
+
+impl fmt::Display for ColorChoiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid color choice '{}', expected auto, always or never", self.0)
+    }
+}
+
+impl Error for ColorChoiceError {}
+
+impl ColorChoice {
+    pub fn parse(value: &str) -> Result<ColorChoice, ColorChoiceError> {
+        match value {
+            "auto" | "tty" | "if-tty" => Ok(ColorChoice::Auto),
+            "always" | "yes" | "force" => Ok(ColorChoice::Always),
+            "never" | "no" | "none" => Ok(ColorChoice::Never),
+            other => Err(ColorChoiceError(other.to_string())),
+        }
+    }
+
+    pub fn enabled(self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal,
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+// SGR codes for each part of the output, configured the same way as GREP_COLORS:
+//   MINIGREP_COLORS="ms=01;31:mc=01;31:fn=35:ln=32:bn=32:se=36"
+//   ms  matched text in selected lines     mc  matched text in context lines
+//   mt  sets both ms and mc                fn  file names
+//   ln  line numbers                       bn  byte offsets
+//   se  separators (`:`, `-` and `--`)
+// An empty value turns coloring of that part off. Unknown keys are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colors {
+    pub selected_match: String,
+    pub context_match: String,
+    pub file_name: String,
+    pub line_number: String,
+    pub byte_offset: String,
+    pub separator: String,
+}
+
+impl Default for Colors {
+    // the same defaults as grep
+    fn default() -> Colors {
+        Colors {
+            selected_match: "01;31".to_string(),
+            context_match: "01;31".to_string(),
+            file_name: "35".to_string(),
+            line_number: "32".to_string(),
+            byte_offset: "32".to_string(),
+            separator: "36".to_string(),
+        }
+    }
+}
+
+impl Colors {
+    // Starts from the defaults and applies the settings in spec on top
+    pub fn parse(spec: &str) -> Colors {
+        let mut colors = Colors::default();
+        for setting in spec.split(':') {
+            let Some((key, value)) = setting.split_once('=') else {
+                continue;
+            };
+            // only digits and `;` are allowed, anything else could be used to inject escape sequences
+            if !value.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                continue;
+            }
+            let value = value.to_string();
+            match key {
+                "ms" => colors.selected_match = value,
+                "mc" => colors.context_match = value,
+                "mt" => {
+                    colors.selected_match = value.clone();
+                    colors.context_match = value;
+                }
+                "fn" => colors.file_name = value,
+                "ln" => colors.line_number = value,
+                "bn" => colors.byte_offset = value,
+                "se" => colors.separator = value,
+                _ => {}
+            }
+        }
+        colors
+    }
+}
+
+// Wraps text in the escape sequences for code. An empty code means no color.
+pub fn paint(code: &str, text: &str) -> String {
+    if code.is_empty() {
+        text.to_string()
+    } else {
+        format!("\x1b[{code}m\x1b[K{text}\x1b[m\x1b[K")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choice() {
+        assert_eq!(Ok(ColorChoice::Always), ColorChoice::parse("always"));
+        assert_eq!(Err(ColorChoiceError("sometimes".to_string())), ColorChoice::parse("sometimes"));
+        assert!(ColorChoice::Auto.enabled(true));
+        assert!(!ColorChoice::Auto.enabled(false));
+        assert!(ColorChoice::Always.enabled(false));
+        assert!(!ColorChoice::Never.enabled(true));
+    }
+
+    #[test]
+    fn parse_spec() {
+        let colors = Colors::parse("mt=04;33:fn=:ln=1\x1b[31m:xx=1");
+        assert_eq!("04;33", colors.selected_match);
+        assert_eq!("04;33", colors.context_match);
+        assert_eq!("", colors.file_name);
+        // rejected, so the default is kept
+        assert_eq!("32", colors.line_number);
+        assert_eq!("36", colors.separator);
+    }
+
+    #[test]
+    fn painting() {
+        assert_eq!("\x1b[01;31m\x1b[Ksafe\x1b[m\x1b[K", paint("01;31", "safe"));
+        assert_eq!("safe", paint("", "safe"));
+    }
+}
@@ -0,0 +1,172 @@
+use std::io::{self, BufRead, Read};
+
+// Text that isn't plain UTF-8, recognized by the byte order mark it starts with:
+//   EF BB BF   UTF-8, the mark is just dropped
+//   FF FE      UTF-16, little endian, as written by most Windows tools
+//   FE FF      UTF-16, big endian
+// Files without a mark are read as UTF-8.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Bom {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Bom {
+    pub fn size(self) -> usize {
+        match self {
+            Bom::Utf8 => 3,
+            Bom::Utf16Le | Bom::Utf16Be => 2,
+        }
+    }
+}
+
+pub fn detect(bytes: &[u8]) -> Option<Bom> {
+    if bytes.starts_with(&[0xef, 0xbb, 0xbf]) {
+        Some(Bom::Utf8)
+    } else if bytes.starts_with(&[0xff, 0xfe]) {
+        Some(Bom::Utf16Le)
+    } else if bytes.starts_with(&[0xfe, 0xff]) {
+        Some(Bom::Utf16Be)
+    } else {
+        None
+    }
+}
+
+// Reads UTF-16 from the inner reader as UTF-8, a buffer of input at a time.
+// Unpaired surrogates and a dangling odd byte at the end become U+FFFD, the
+// same as String::from_utf16_lossy would make them.
+pub struct Utf16Decoder<R: BufRead> {
+    inner: R,
+    big_endian: bool,
+    // the first byte of a unit whose second byte is in the next buffer
+    odd: Option<u8>,
+    // a high surrogate waiting for the low one after it
+    high: Option<u16>,
+    // decoded and not read yet
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: BufRead> Utf16Decoder<R> {
+    // The byte order mark should already have been consumed
+    pub fn new(inner: R, big_endian: bool) -> Utf16Decoder<R> {
+        Utf16Decoder {
+            inner,
+            big_endian,
+            odd: None,
+            high: None,
+            out: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        let mut buf = [0; 4];
+        self.out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+    }
+
+    fn unit(&mut self, unit: u16) {
+        match unit {
+            0xd800..=0xdbff => {
+                if self.high.replace(unit).is_some() {
+                    self.push(char::REPLACEMENT_CHARACTER);
+                }
+            }
+            0xdc00..=0xdfff => match self.high.take() {
+                Some(high) => {
+                    let c = 0x10000 + ((u32::from(high) - 0xd800) << 10) + (u32::from(unit) - 0xdc00);
+                    self.push(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER));
+                }
+                None => self.push(char::REPLACEMENT_CHARACTER),
+            },
+            _ => {
+                if self.high.take().is_some() {
+                    self.push(char::REPLACEMENT_CHARACTER);
+                }
+                // not a surrogate, so always a valid char
+                self.push(char::from_u32(u32::from(unit)).unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+        }
+    }
+
+    // Decodes the next buffer of input. Returns false at the end of it.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.out.clear();
+        self.pos = 0;
+        let input = self.inner.fill_buf()?.to_vec();
+        if input.is_empty() {
+            if self.high.take().is_some() || self.odd.take().is_some() {
+                self.push(char::REPLACEMENT_CHARACTER);
+            }
+            return Ok(!self.out.is_empty());
+        }
+        self.inner.consume(input.len());
+        let mut bytes = input.iter().copied();
+        while let Some(first) = self.odd.take().or_else(|| bytes.next()) {
+            let Some(second) = bytes.next() else {
+                self.odd = Some(first);
+                break;
+            };
+            let pair = [first, second];
+            self.unit(if self.big_endian { u16::from_be_bytes(pair) } else { u16::from_le_bytes(pair) });
+        }
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Read for Utf16Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() {
+            if !self.fill()? {
+                return Ok(0);
+            }
+        }
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    fn utf16(text: &str, big_endian: bool) -> Vec<u8> {
+        text.encode_utf16()
+            .flat_map(|unit| if big_endian { unit.to_be_bytes() } else { unit.to_le_bytes() })
+            .collect()
+    }
+
+    fn decode(bytes: &[u8], big_endian: bool) -> String {
+        let mut text = String::new();
+        // a tiny buffer so units and surrogate pairs get split between reads
+        Utf16Decoder::new(BufReader::with_capacity(3, bytes), big_endian)
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[test]
+    fn detects_byte_order_marks() {
+        assert_eq!(Some(Bom::Utf8), detect(b"\xef\xbb\xbfRust"));
+        assert_eq!(Some(Bom::Utf16Le), detect(b"\xff\xfeR\0"));
+        assert_eq!(Some(Bom::Utf16Be), detect(b"\xfe\xff\0R"));
+        assert_eq!(None, detect(b"Rust"));
+        assert_eq!(None, detect(b"\xff"));
+    }
+
+    #[test]
+    fn decodes_utf16() {
+        let text = "Rust:\r\nsafe, fast, productive 🦀\nPick three";
+        assert_eq!(text, decode(&utf16(text, false), false));
+        assert_eq!(text, decode(&utf16(text, true), true));
+
+        // a lone surrogate and a missing last byte
+        let mut bytes = utf16("a", false);
+        bytes.extend_from_slice(&[0x00, 0xd8, b'b', 0, b'c']);
+        assert_eq!("a\u{fffd}b\u{fffd}", decode(&bytes, false));
+    }
+}
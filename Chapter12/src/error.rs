@@ -0,0 +1,177 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::path::PathBuf;
+
+use crate::args::ArgError;
+use crate::glob::GlobError;
+use crate::regex::RegexError;
+use crate::FuzzyError;
+
+// Everything that can stop minigrep, grouped so that main can tell the
+// kinds of failure apart and exit with a different code for each
+#[derive(Debug)]
+pub enum MinigrepError {
+    // the command line didn't make sense
+    Args(ArgError),
+    // the search pattern isn't a valid regex
+    Pattern(RegexError),
+    // one of several -e or -f patterns isn't a valid regex, index counts from 1
+    PatternAt {
+        index: usize,
+        pattern: String,
+        source: RegexError,
+    },
+    // the query can't be used for --fuzzy
+    Fuzzy(FuzzyError),
+    // an --include or --exclude glob is invalid
+    Glob(GlobError),
+    // a file or directory couldn't be read, or written back with --write
+    Io { path: PathBuf, source: io::Error },
+    // the results couldn't be written to stdout
+    Output(io::Error),
+    // some of the inputs couldn't be searched, the others were
+    Incomplete {
+        failed: usize,
+        inputs: usize,
+        worst: Box<MinigrepError>,
+    },
+}
+
+impl MinigrepError {
+    pub fn io(path: impl Into<PathBuf>, source: io::Error) -> MinigrepError {
+        MinigrepError::Io {
+            path: path.into(),
+            source,
+        }
+    }
+
+    // A different exit code for each kind of failure, so scripts can tell them apart:
+    //   0  the search ran, whether or not anything matched
+    //   2  invalid command line
+    //   3  invalid regex, given on its own or with -e or -f
+    //   4  a file or directory doesn't exist
+    //   5  permission denied on a file or directory
+    //   6  any other error reading a file, or writing it back with --write
+    //   7  the output couldn't be written
+    //   8  the query can't be used for --fuzzy
+    //   9  invalid --include or --exclude glob
+    // When only some of the inputs failed it's the code of the worst of them.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            MinigrepError::Args(_) => 2,
+            MinigrepError::Pattern(_) | MinigrepError::PatternAt { .. } => 3,
+            MinigrepError::Io { source, .. } => match source.kind() {
+                ErrorKind::NotFound => 4,
+                ErrorKind::PermissionDenied => 5,
+                _ => 6,
+            },
+            MinigrepError::Output(_) => 7,
+            MinigrepError::Fuzzy(_) => 8,
+            MinigrepError::Glob(_) => 9,
+            MinigrepError::Incomplete { worst, .. } => worst.exit_code(),
+        }
+    }
+}
+
+impl fmt::Display for MinigrepError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MinigrepError::Args(e) => write!(f, "{e}"),
+            MinigrepError::Pattern(e) => write!(f, "invalid pattern: {e}"),
+            MinigrepError::PatternAt { index, pattern, source } => {
+                write!(f, "invalid pattern {index} '{pattern}': {source}")
+            }
+            MinigrepError::Fuzzy(e) => write!(f, "invalid pattern: {e}"),
+            MinigrepError::Glob(e) => write!(f, "{e}"),
+            MinigrepError::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            MinigrepError::Output(e) => write!(f, "can't write output: {e}"),
+            MinigrepError::Incomplete { failed, inputs, .. } => {
+                write!(f, "{failed} of {inputs} inputs couldn't be searched")
+            }
+        }
+    }
+}
+
+impl Error for MinigrepError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MinigrepError::Args(e) => Some(e),
+            MinigrepError::Pattern(e) => Some(e),
+            MinigrepError::PatternAt { source, .. } => Some(source),
+            MinigrepError::Fuzzy(e) => Some(e),
+            MinigrepError::Glob(e) => Some(e),
+            MinigrepError::Io { source, .. } => Some(source),
+            MinigrepError::Output(e) => Some(e),
+            MinigrepError::Incomplete { worst, .. } => Some(worst.as_ref()),
+        }
+    }
+}
+
+// so that `?` works on the errors of each step
+
+impl From<ArgError> for MinigrepError {
+    fn from(e: ArgError) -> MinigrepError {
+        MinigrepError::Args(e)
+    }
+}
+
+impl From<RegexError> for MinigrepError {
+    fn from(e: RegexError) -> MinigrepError {
+        MinigrepError::Pattern(e)
+    }
+}
+
+impl From<FuzzyError> for MinigrepError {
+    fn from(e: FuzzyError) -> MinigrepError {
+        MinigrepError::Fuzzy(e)
+    }
+}
+
+impl From<GlobError> for MinigrepError {
+    fn from(e: GlobError) -> MinigrepError {
+        MinigrepError::Glob(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_and_source() {
+        let e = MinigrepError::io("logs/app.log", io::Error::new(ErrorKind::NotFound, "No such file or directory"));
+        assert_eq!("logs/app.log: No such file or directory", e.to_string());
+        let source = e.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(ErrorKind::NotFound, source.kind());
+
+        let e = MinigrepError::from(RegexError::UnmatchedParen(3));
+        assert_eq!("invalid pattern: unmatched ')' at position 3", e.to_string());
+        assert_eq!(Some(&RegexError::UnmatchedParen(3)), e.source().unwrap().downcast_ref::<RegexError>());
+
+        let e = MinigrepError::from(ArgError::MissingQuery);
+        assert_eq!("Did not get query string", e.to_string());
+    }
+
+    #[test]
+    fn exit_codes() {
+        let io = |kind: ErrorKind| MinigrepError::io("app.log", io::Error::from(kind));
+        let errors = [
+            MinigrepError::from(ArgError::MissingQuery),
+            MinigrepError::from(RegexError::UnmatchedParen(3)),
+            io(ErrorKind::NotFound),
+            io(ErrorKind::PermissionDenied),
+            io(ErrorKind::InvalidData),
+            MinigrepError::Output(io::Error::from(ErrorKind::BrokenPipe)),
+            MinigrepError::from(FuzzyError::TooLong { len: 100, folded: 100 }),
+            MinigrepError::from(crate::glob::Glob::new("*.{rs").unwrap_err()),
+        ];
+        let codes: Vec<i32> = errors.iter().map(MinigrepError::exit_code).collect();
+        assert_eq!(vec![2, 3, 4, 5, 6, 7, 8, 9], codes);
+
+        let e = MinigrepError::PatternAt { index: 2, pattern: "(".to_string(), source: RegexError::UnclosedGroup(0) };
+        assert_eq!(3, e.exit_code());
+        let e = MinigrepError::Incomplete { failed: 1, inputs: 2, worst: Box::new(io(ErrorKind::PermissionDenied)) };
+        assert_eq!(5, e.exit_code());
+    }
+}
@@ -0,0 +1,264 @@
+use std::error::Error;
+use std::fmt;
+
+// Shell style glob patterns used to include or exclude files.
+// Supported syntax:
+//   ?        any single character except `/`
+//   *        any run of characters except `/`
+//   **       any run of characters including `/` (so `src/**/*.rs` and `**/test_*`)
+//   [a-z]    character classes, negated with `[!...]` or `[^...]`
+//   {rs,md}  alternatives
+//   \x       the literal character x
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GlobError {
+    UnclosedClass(String),
+    UnclosedBrace(String),
+}
+
+impl fmt::Display for GlobError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GlobError::UnclosedClass(pattern) => write!(f, "unclosed character class in glob '{pattern}'"),
+            GlobError::UnclosedBrace(pattern) => write!(f, "unclosed '{{' in glob '{pattern}'"),
+        }
+    }
+}
+
+impl Error for GlobError {}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Char(char),
+    AnyChar,
+    Star,
+    // `**`: matches across directory separators
+    DoubleStar,
+    // `**/`: matches zero or more whole directories
+    AnyDirs,
+    Class { ranges: Vec<(char, char)>, negated: bool },
+}
+
+#[derive(Debug, Clone)]
+pub struct Glob {
+    pattern: String,
+    // one token list per brace alternative
+    alternatives: Vec<Vec<Token>>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Result<Glob, GlobError> {
+        let alternatives = expand_braces(pattern)?
+            .iter()
+            .map(|p| tokenize(p, pattern))
+            .collect::<Result<_, _>>()?;
+        Ok(Glob {
+            pattern: pattern.to_string(),
+            alternatives,
+        })
+    }
+
+    // Globs without a `/` are matched against the file name only,
+    // the others against the whole relative path
+    pub fn has_separator(&self) -> bool {
+        self.pattern.contains('/')
+    }
+
+    // Matches the whole of `text` against the pattern
+    pub fn is_match(&self, text: &str) -> bool {
+        let text: Vec<char> = text.chars().collect();
+        self.alternatives.iter().any(|tokens| match_tokens(tokens, &text))
+    }
+}
+
+// Expands `a{b,c}d` into `abd` and `acd`. Nested braces are supported.
+fn expand_braces(pattern: &str) -> Result<Vec<String>, GlobError> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut depth = 0;
+    let mut open = None;
+    let mut commas = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '{' => {
+                if depth == 0 {
+                    open = Some(i);
+                }
+                depth += 1;
+            }
+            ',' if depth == 1 => commas.push(i),
+            '}' if depth > 0 => {
+                depth -= 1;
+                if depth == 0 {
+                    let open = open.unwrap();
+                    let prefix: String = chars[..open].iter().collect();
+                    let suffix: String = chars[i + 1..].iter().collect();
+                    let mut bounds = vec![open];
+                    bounds.extend(&commas);
+                    bounds.push(i);
+                    let mut expanded = Vec::new();
+                    for pair in bounds.windows(2) {
+                        let middle: String = chars[pair[0] + 1..pair[1]].iter().collect();
+                        for tail in expand_braces(&format!("{middle}{suffix}"))? {
+                            expanded.push(format!("{prefix}{tail}"));
+                        }
+                    }
+                    return Ok(expanded);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if depth > 0 {
+        return Err(GlobError::UnclosedBrace(pattern.to_string()));
+    }
+    Ok(vec![pattern.to_string()])
+}
+
+fn tokenize(pattern: &str, original: &str) -> Result<Vec<Token>, GlobError> {
+    let chars: Vec<char> = pattern.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '?' => tokens.push(Token::AnyChar),
+            '*' => {
+                if chars.get(i + 1) == Some(&'*') {
+                    i += 1;
+                    if chars.get(i + 1) == Some(&'/') {
+                        i += 1;
+                        tokens.push(Token::AnyDirs);
+                    } else {
+                        tokens.push(Token::DoubleStar);
+                    }
+                } else {
+                    tokens.push(Token::Star);
+                }
+            }
+            '[' => {
+                let (token, next) = parse_class(&chars, i)
+                    .ok_or_else(|| GlobError::UnclosedClass(original.to_string()))?;
+                tokens.push(token);
+                i = next;
+                continue;
+            }
+            '\\' if i + 1 < chars.len() => {
+                i += 1;
+                tokens.push(Token::Char(chars[i]));
+            }
+            c => tokens.push(Token::Char(c)),
+        }
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+// Parses the class starting at chars[start] == '['.
+// Returns the token and the index just past the closing `]`.
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut i = start + 1;
+    let negated = matches!(chars.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let mut c = *chars.get(i)?;
+        if c == ']' && !first {
+            return Some((Token::Class { ranges, negated }, i + 1));
+        }
+        first = false;
+        if c == '\\' {
+            i += 1;
+            c = *chars.get(i)?;
+        }
+        if chars.get(i + 1) == Some(&'-') && chars.get(i + 2).is_some_and(|&n| n != ']') {
+            ranges.push((c, chars[i + 2]));
+            i += 3;
+        } else {
+            ranges.push((c, c));
+            i += 1;
+        }
+    }
+}
+
+fn match_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+    match token {
+        Token::Char(c) => text.first() == Some(c) && match_tokens(rest, &text[1..]),
+        Token::AnyChar => text.first().is_some_and(|&c| c != '/') && match_tokens(rest, &text[1..]),
+        Token::Class { ranges, negated } => match text.first() {
+            Some(&c) if c != '/' => {
+                let hit = ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi);
+                hit != *negated && match_tokens(rest, &text[1..])
+            }
+            _ => false,
+        },
+        Token::Star => {
+            // try every split point up to the next separator
+            let limit = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+            (0..=limit).any(|n| match_tokens(rest, &text[n..]))
+        }
+        Token::DoubleStar => (0..=text.len()).any(|n| match_tokens(rest, &text[n..])),
+        Token::AnyDirs => {
+            // zero directories, or skip to just after any separator
+            match_tokens(rest, text)
+                || text
+                    .iter()
+                    .enumerate()
+                    .any(|(n, &c)| c == '/' && match_tokens(rest, &text[n + 1..]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        let glob = Glob::new("*.rs").unwrap();
+        assert!(glob.is_match("lib.rs"));
+        assert!(!glob.is_match("lib.rs.bak"));
+        assert!(!glob.is_match("src/lib.rs"));
+
+        let glob = Glob::new("file?.txt").unwrap();
+        assert!(glob.is_match("file1.txt"));
+        assert!(!glob.is_match("file10.txt"));
+    }
+
+    #[test]
+    fn double_star() {
+        let glob = Glob::new("src/**/*.rs").unwrap();
+        assert!(glob.is_match("src/lib.rs"));
+        assert!(glob.is_match("src/a/b/c.rs"));
+        assert!(!glob.is_match("tests/a.rs"));
+
+        let glob = Glob::new("target/**").unwrap();
+        assert!(glob.is_match("target/debug/minigrep"));
+    }
+
+    #[test]
+    fn classes_and_braces() {
+        let glob = Glob::new("[a-c]*.{rs,toml}").unwrap();
+        assert!(glob.is_match("build.rs"));
+        assert!(!glob.is_match("Cargo.toml"));
+        assert!(glob.is_match("cargo.toml"));
+        assert!(!glob.is_match("main.rs"));
+
+        let glob = Glob::new("[!.]*").unwrap();
+        assert!(glob.is_match("poem.txt"));
+        assert!(!glob.is_match(".gitignore"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(GlobError::UnclosedClass("[abc".to_string()), Glob::new("[abc").unwrap_err());
+        assert_eq!(GlobError::UnclosedBrace("*.{rs".to_string()), Glob::new("*.{rs").unwrap_err());
+    }
+}
@@ -0,0 +1,152 @@
+use std::fs;
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+use crate::glob::Glob;
+
+// The rules of one .gitignore or .ignore file.
+// Supported syntax, following gitignore(5):
+//   # comment     blank lines and comments are skipped, `\#` is a literal #
+//   *.log         a pattern without `/` matches the name at any depth
+//   /todo.txt     a pattern with a `/` at the start or in the middle is
+//   doc/*.txt       relative to the directory holding the ignore file
+//   build/        a trailing `/` only matches directories
+//   !keep.log     `!` re-includes what an earlier rule ignored, `\!` is a literal !
+//   a/**/b        `**` matches any number of directories
+// When several rules match, the last one wins.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Ignored,
+    // matched by a `!` rule
+    Whitelisted,
+}
+
+#[derive(Debug, Clone)]
+struct Rule {
+    glob: Glob,
+    negated: bool,
+    dir_only: bool,
+    anchored: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Gitignore {
+    rules: Vec<Rule>,
+}
+
+impl Gitignore {
+    pub fn parse(contents: &str) -> Gitignore {
+        Gitignore {
+            rules: contents.lines().filter_map(parse_rule).collect(),
+        }
+    }
+
+    // A missing file just has no rules
+    pub fn load(path: &Path) -> io::Result<Gitignore> {
+        match fs::read_to_string(path) {
+            Ok(contents) => Ok(Gitignore::parse(&contents)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Gitignore::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // What the rules say about a path relative to the ignore file's directory,
+    // or None if no rule matches it
+    pub fn matched(&self, relative: &str, is_dir: bool) -> Option<Verdict> {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        self.rules
+            .iter()
+            .rev()
+            .filter(|rule| is_dir || !rule.dir_only)
+            .find(|rule| rule.glob.is_match(if rule.anchored { relative } else { name }))
+            .map(|rule| if rule.negated { Verdict::Whitelisted } else { Verdict::Ignored })
+    }
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    if line.starts_with('#') {
+        return None;
+    }
+    // trailing spaces are dropped unless escaped with a backslash
+    let mut line = line;
+    while line.ends_with(' ') && !line.ends_with("\\ ") {
+        line = &line[..line.len() - 1];
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let anchored = line.contains('/');
+    let line = line.strip_prefix('/').unwrap_or(line);
+    if line.is_empty() {
+        return None;
+    }
+    // braces have no special meaning in gitignore, unlike in the glob module
+    let pattern = line.replace('{', "\\{").replace('}', "\\}");
+    // git skips patterns it can't parse, so do the same
+    let glob = Glob::new(&pattern).ok()?;
+    Some(Rule {
+        glob,
+        negated,
+        dir_only,
+        anchored,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_at_any_depth() {
+        let ignore = Gitignore::parse("# build output\n\n*.log\ntarget/\n");
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("debug.log", false));
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("logs/today/debug.log", false));
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("crates/foo/target", true));
+        // target/ only matches directories
+        assert_eq!(None, ignore.matched("target", false));
+        assert_eq!(None, ignore.matched("# build output", false));
+    }
+
+    #[test]
+    fn anchored_patterns() {
+        let ignore = Gitignore::parse("/todo.txt\ndoc/*.txt\na/**/b\n");
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("todo.txt", false));
+        assert_eq!(None, ignore.matched("src/todo.txt", false));
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("doc/notes.txt", false));
+        assert_eq!(None, ignore.matched("doc/api/notes.txt", false));
+        assert_eq!(None, ignore.matched("src/doc/notes.txt", false));
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("a/b", false));
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("a/x/y/b", true));
+    }
+
+    #[test]
+    fn negation() {
+        let ignore = Gitignore::parse("*.log\n!keep.log\n\\!important\n");
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("debug.log", false));
+        assert_eq!(Some(Verdict::Whitelisted), ignore.matched("keep.log", false));
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("!important", false));
+
+        // the last matching rule wins
+        let ignore = Gitignore::parse("!keep.log\n*.log\n");
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("keep.log", false));
+    }
+
+    #[test]
+    fn escapes_and_spaces() {
+        let ignore = Gitignore::parse("\\#notes   \ntrailing\\ \n{a,b}\n");
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("#notes", false));
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("trailing ", false));
+        assert_eq!(Some(Verdict::Ignored), ignore.matched("{a,b}", false));
+        assert_eq!(None, ignore.matched("a", false));
+    }
+}
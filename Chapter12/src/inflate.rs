@@ -0,0 +1,599 @@
+use std::io::{self, BufRead, ErrorKind, Read};
+
+// Decompression of gzip and zlib streams, so compressed logs can be searched
+// as they are. Both wrap data compressed with DEFLATE (RFC 1951):
+//   gzip (RFC 1952)  starts with the magic bytes 1f 8b, ends with a CRC-32
+//   zlib (RFC 1950)  starts with a two byte header like 78 9c, ends with an Adler-32
+// A gzip file can hold several members back to back, as `cat a.gz b.gz` makes.
+//
+// DEFLATE data is a series of blocks. Each block is either stored as is or
+// compressed with Huffman codes for literal bytes and for back-references
+// (length, distance) to output up to 32K bytes back.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Gzip,
+    Zlib,
+}
+
+// Recognises compressed data from its first bytes.
+// Only the zlib headers written by common compression levels are accepted, a
+// header like 78 5e ("x^") is too likely to be the start of a text file.
+pub fn detect(bytes: &[u8]) -> Option<Format> {
+    match bytes {
+        [0x1f, 0x8b, ..] => Some(Format::Gzip),
+        [0x78, 0x01 | 0x9c | 0xda, ..] => Some(Format::Zlib),
+        _ => None,
+    }
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("corrupt compressed data: {message}"))
+}
+
+// Reads the compressed stream a few bits at a time, least significant bit
+// first. The bits are buffered a word at a time: refilling takes as many
+// whole bytes as fit at once, rather than going back to the reader per byte.
+struct BitReader<R> {
+    inner: R,
+    bits: u64,
+    count: u32,
+}
+
+impl<R: BufRead> BitReader<R> {
+    // Tops the buffer up to at least 57 bits, or as many as are left
+    fn refill(&mut self) -> io::Result<()> {
+        while self.count <= 56 {
+            let available = self.inner.fill_buf()?;
+            if available.is_empty() {
+                break;
+            }
+            let take = available.len().min(((64 - self.count) / 8) as usize);
+            for &byte in &available[..take] {
+                self.bits |= (byte as u64) << self.count;
+                self.count += 8;
+            }
+            self.inner.consume(take);
+        }
+        Ok(())
+    }
+
+    // The next n bits without using them up, up to 32. Near the end of the
+    // stream there may be fewer, the ones missing are zero.
+    fn peek(&mut self, n: u32) -> io::Result<u32> {
+        if self.count < n {
+            self.refill()?;
+        }
+        Ok((self.bits & ((1 << n) - 1)) as u32)
+    }
+
+    fn consume(&mut self, n: u32) -> io::Result<()> {
+        if self.count < n {
+            return Err(io::Error::new(ErrorKind::UnexpectedEof, "compressed data ends early"));
+        }
+        self.bits >>= n;
+        self.count -= n;
+        Ok(())
+    }
+
+    // Up to 32 bits
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        let value = self.peek(n)?;
+        self.consume(n)?;
+        Ok(value)
+    }
+
+    // Skips to the next byte boundary
+    fn align(&mut self) {
+        let skip = self.count % 8;
+        self.bits >>= skip;
+        self.count -= skip;
+    }
+
+    // A whole byte, only used where the stream is at a byte boundary
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.bits(8)? as u8)
+    }
+
+    fn u16_le(&mut self) -> io::Result<u16> {
+        Ok(self.bits(16)? as u16)
+    }
+
+    fn u32_le(&mut self) -> io::Result<u32> {
+        self.bits(32)
+    }
+
+    fn at_end(&mut self) -> io::Result<bool> {
+        Ok(self.count == 0 && self.inner.fill_buf()?.is_empty())
+    }
+}
+
+// A canonical Huffman code, stored as the number of codes of each length
+// and the symbols in code order. The short codes, which are most of what's
+// decoded, are also in a table indexed by the next FAST_BITS bits of input.
+// Longer ones are decoded by walking the code a bit at a time.
+#[derive(Debug, Clone)]
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+    // symbol << 4 | code length, 0 where the code is longer than FAST_BITS
+    fast: Vec<u16>,
+}
+
+const FAST_BITS: u32 = 9;
+
+impl Huffman {
+    // lengths[symbol] is the code length of symbol, 0 if it isn't used
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        // more codes of a length than there's room for
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(corrupt("over-subscribed Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        let mut fast = vec![0; 1 << FAST_BITS];
+        let (mut code, mut index) = (0u32, 0usize);
+        for length in 1..=FAST_BITS {
+            for &symbol in &symbols[index..index + counts[length as usize] as usize] {
+                // codes are sent starting from their most significant bit
+                let reversed = code.reverse_bits() >> (32 - length);
+                for i in (reversed as usize..fast.len()).step_by(1 << length) {
+                    fast[i] = symbol << 4 | length as u16;
+                }
+                code += 1;
+            }
+            index += counts[length as usize] as usize;
+            code <<= 1;
+        }
+        Ok(Huffman { counts, symbols, fast })
+    }
+
+    fn decode<R: BufRead>(&self, bits: &mut BitReader<R>) -> io::Result<u16> {
+        // code is the bits read so far, first the first code of that length,
+        // index where the symbols of that length start
+        let peeked = bits.peek(15)?;
+        let entry = self.fast[(peeked & ((1 << FAST_BITS) - 1)) as usize];
+        if entry != 0 {
+            bits.consume((entry & 0xf) as u32)?;
+            return Ok(entry >> 4);
+        }
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for (length, &count) in (1..).zip(&self.counts[1..]) {
+            code |= ((peeked >> (length - 1)) & 1) as i32;
+            let count = count as i32;
+            if code - first < count {
+                bits.consume(length)?;
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(corrupt("invalid Huffman code"))
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// the order code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// how far back a back-reference can reach
+const WINDOW: usize = 32 * 1024;
+// how much output to decode at a time
+const CHUNK: usize = 32 * 1024;
+
+enum Block {
+    // the next thing to read is a block header, or the trailer after the last block
+    Header,
+    // bytes left in a stored block
+    Stored(usize),
+    // literal/length and distance codes
+    Huffman(Huffman, Huffman),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Checksum {
+    Crc32(u32),
+    // the two running sums
+    Adler32(u32, u32),
+}
+
+impl Checksum {
+    fn new(format: Format) -> Checksum {
+        match format {
+            Format::Gzip => Checksum::Crc32(0),
+            Format::Zlib => Checksum::Adler32(1, 0),
+        }
+    }
+
+    fn update(&mut self, byte: u8) {
+        match self {
+            Checksum::Crc32(crc) => {
+                let c = !*crc;
+                *crc = !(CRC_TABLE[((c ^ byte as u32) & 0xff) as usize] ^ (c >> 8));
+            }
+            Checksum::Adler32(a, b) => {
+                *a = (*a + byte as u32) % 65521;
+                *b = (*b + *a) % 65521;
+            }
+        }
+    }
+
+    fn value(self) -> u32 {
+        match self {
+            Checksum::Crc32(crc) => crc,
+            Checksum::Adler32(a, b) => (b << 16) | a,
+        }
+    }
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 { 0xedb8_8320 ^ (c >> 1) } else { c >> 1 };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+// Reads decompressed data out of a gzip or zlib stream. The data is decoded
+// a chunk at a time as it's read, so memory use doesn't grow with the file.
+pub struct Decoder<R> {
+    bits: BitReader<R>,
+    format: Format,
+    block: Block,
+    last_block: bool,
+    // decoded output, keeping the last WINDOW bytes already read for back-references
+    history: Vec<u8>,
+    // start of the output that hasn't been read yet
+    pos: usize,
+    checksum: Checksum,
+    // bytes in the current gzip member, modulo 2^32 like the trailer
+    size: u32,
+    done: bool,
+}
+
+impl<R: BufRead> Decoder<R> {
+    pub fn new(reader: R, format: Format) -> io::Result<Decoder<R>> {
+        let mut decoder = Decoder {
+            bits: BitReader { inner: reader, bits: 0, count: 0 },
+            format,
+            block: Block::Header,
+            last_block: false,
+            history: Vec::new(),
+            pos: 0,
+            checksum: Checksum::new(format),
+            size: 0,
+            done: false,
+        };
+        decoder.read_header()?;
+        Ok(decoder)
+    }
+
+    fn read_header(&mut self) -> io::Result<()> {
+        match self.format {
+            Format::Gzip => self.read_gzip_header(),
+            Format::Zlib => {
+                let (cmf, flg) = (self.bits.byte()?, self.bits.byte()?);
+                if cmf & 0x0f != 8 || !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+                    return Err(corrupt("invalid zlib header"));
+                }
+                if flg & 0x20 != 0 {
+                    return Err(corrupt("zlib preset dictionaries aren't supported"));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn read_gzip_header(&mut self) -> io::Result<()> {
+        const FHCRC: u8 = 0x02;
+        const FEXTRA: u8 = 0x04;
+        const FNAME: u8 = 0x08;
+        const FCOMMENT: u8 = 0x10;
+
+        if self.bits.byte()? != 0x1f || self.bits.byte()? != 0x8b || self.bits.byte()? != 8 {
+            return Err(corrupt("invalid gzip header"));
+        }
+        let flags = self.bits.byte()?;
+        if flags & 0xe0 != 0 {
+            return Err(corrupt("reserved gzip flags are set"));
+        }
+        // modification time, extra flags and operating system
+        for _ in 0..6 {
+            self.bits.byte()?;
+        }
+        if flags & FEXTRA != 0 {
+            let length = self.bits.u16_le()?;
+            for _ in 0..length {
+                self.bits.byte()?;
+            }
+        }
+        // the original file name and a comment, both zero terminated
+        for flag in [FNAME, FCOMMENT] {
+            if flags & flag != 0 {
+                while self.bits.byte()? != 0 {}
+            }
+        }
+        if flags & FHCRC != 0 {
+            self.bits.u16_le()?;
+        }
+        Ok(())
+    }
+
+    // Checks the trailer of the stream, then moves on to the next gzip member if there is one
+    fn read_trailer(&mut self) -> io::Result<()> {
+        self.bits.align();
+        match self.format {
+            Format::Gzip => {
+                let crc = self.bits.u32_le()?;
+                let size = self.bits.u32_le()?;
+                if crc != self.checksum.value() || size != self.size {
+                    return Err(corrupt("gzip checksum doesn't match"));
+                }
+                if self.bits.at_end()? {
+                    self.done = true;
+                } else {
+                    self.read_gzip_header()?;
+                    self.last_block = false;
+                    self.checksum = Checksum::new(self.format);
+                    self.size = 0;
+                }
+            }
+            Format::Zlib => {
+                let adler = self.bits.u32_le()?.swap_bytes();
+                if adler != self.checksum.value() {
+                    return Err(corrupt("zlib checksum doesn't match"));
+                }
+                self.done = true;
+            }
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, byte: u8) {
+        self.history.push(byte);
+        self.checksum.update(byte);
+        self.size = self.size.wrapping_add(1);
+    }
+
+    // Decodes the next piece of the stream: a block header, one stored byte or
+    // the symbols of a Huffman block up to the end of the chunk
+    fn step(&mut self) -> io::Result<()> {
+        match std::mem::replace(&mut self.block, Block::Header) {
+            Block::Header if self.last_block => self.read_trailer()?,
+            Block::Header => {
+                self.last_block = self.bits.bits(1)? == 1;
+                self.block = match self.bits.bits(2)? {
+                    0 => {
+                        self.bits.align();
+                        let length = self.bits.u16_le()?;
+                        if length != !self.bits.u16_le()? {
+                            return Err(corrupt("stored block length doesn't match its complement"));
+                        }
+                        Block::Stored(length as usize)
+                    }
+                    1 => fixed_codes()?,
+                    2 => self.dynamic_codes()?,
+                    _ => return Err(corrupt("invalid block type")),
+                };
+            }
+            Block::Stored(0) => {}
+            Block::Stored(left) => {
+                let byte = self.bits.byte()?;
+                self.push(byte);
+                self.block = Block::Stored(left - 1);
+            }
+            Block::Huffman(literals, distances) => {
+                if !self.symbols(&literals, &distances)? {
+                    self.block = Block::Huffman(literals, distances);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Decodes the symbols of a Huffman block until there's a CHUNK of output.
+    // Returns whether the end of the block was reached.
+    fn symbols(&mut self, literals: &Huffman, distances: &Huffman) -> io::Result<bool> {
+        while self.history.len() - self.pos < CHUNK {
+            match literals.decode(&mut self.bits)? {
+                literal @ 0..=255 => self.push(literal as u8),
+                // end of block
+                256 => return Ok(true),
+                symbol @ 257..=285 => {
+                    let symbol = symbol as usize - 257;
+                    let length = LENGTH_BASE[symbol] as usize + self.bits.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+                    let symbol = distances.decode(&mut self.bits)? as usize;
+                    if symbol >= DISTANCE_BASE.len() {
+                        return Err(corrupt("invalid distance code"));
+                    }
+                    let distance =
+                        DISTANCE_BASE[symbol] as usize + self.bits.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+                    if distance > self.history.len() {
+                        return Err(corrupt("distance too far back"));
+                    }
+                    // the copy can overlap what it's copying, so go a byte at a time
+                    for _ in 0..length {
+                        self.push(self.history[self.history.len() - distance]);
+                    }
+                }
+                _ => return Err(corrupt("invalid literal/length code")),
+            }
+        }
+        Ok(false)
+    }
+
+    // The codes of a dynamic block are themselves stored as code lengths,
+    // compressed with a third Huffman code
+    fn dynamic_codes(&mut self) -> io::Result<Block> {
+        let literal_count = self.bits.bits(5)? as usize + 257;
+        let distance_count = self.bits.bits(5)? as usize + 1;
+        let code_length_count = self.bits.bits(4)? as usize + 4;
+        if literal_count > 286 || distance_count > 30 {
+            return Err(corrupt("too many codes"));
+        }
+        let mut code_lengths = [0u8; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..code_length_count] {
+            code_lengths[symbol] = self.bits.bits(3)? as u8;
+        }
+        let code_lengths = Huffman::new(&code_lengths)?;
+
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let (length, repeat) = match code_lengths.decode(&mut self.bits)? {
+                length @ 0..=15 => (length as u8, 1),
+                16 => {
+                    let previous = *lengths.last().ok_or_else(|| corrupt("repeat with no previous length"))?;
+                    (previous, 3 + self.bits.bits(2)?)
+                }
+                17 => (0, 3 + self.bits.bits(3)?),
+                _ => (0, 11 + self.bits.bits(7)?),
+            };
+            if lengths.len() + repeat as usize > literal_count + distance_count {
+                return Err(corrupt("too many code lengths"));
+            }
+            lengths.extend(std::iter::repeat_n(length, repeat as usize));
+        }
+        if lengths[256] == 0 {
+            return Err(corrupt("no end of block code"));
+        }
+        let (literals, distances) = lengths.split_at(literal_count);
+        Ok(Block::Huffman(Huffman::new(literals)?, Huffman::new(distances)?))
+    }
+
+    // Decodes up to another CHUNK of output
+    fn fill(&mut self) -> io::Result<()> {
+        // forget output that has been read and is too far back to be referred to
+        if self.pos > WINDOW {
+            let old = self.pos - WINDOW;
+            self.history.drain(..old);
+            self.pos -= old;
+        }
+        while !self.done && self.history.len() - self.pos < CHUNK {
+            self.step()?;
+        }
+        Ok(())
+    }
+}
+
+// The codes that blocks of type 1 use instead of sending their own
+fn fixed_codes() -> io::Result<Block> {
+    let mut literals = [8u8; 288];
+    literals[144..256].fill(9);
+    literals[256..280].fill(7);
+    Ok(Block::Huffman(Huffman::new(&literals)?, Huffman::new(&[5; 30])?))
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.history.len() {
+            self.fill()?;
+        }
+        let n = buf.len().min(self.history.len() - self.pos);
+        buf[..n].copy_from_slice(&self.history[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decompress(data: &[u8]) -> io::Result<String> {
+        let format = detect(data).expect("not compressed");
+        let mut out = String::new();
+        Decoder::new(data, format)?.read_to_string(&mut out)?;
+        Ok(out)
+    }
+
+    // the test data was made with Python's zlib and gzip modules
+    const STORED: [u8; 35] = [
+        0x78, 0x01, 0x01, 0x18, 0x00, 0xe7, 0xff, 0x73, 0x61, 0x66, 0x65, 0x2c, 0x20, 0x66, 0x61, 0x73, 0x74, 0x2c,
+        0x20, 0x70, 0x72, 0x6f, 0x64, 0x75, 0x63, 0x74, 0x69, 0x76, 0x65, 0x2e, 0x0a, 0x6a, 0xa4, 0x08, 0x63,
+    ];
+    const FIXED: [u8; 17] = [
+        0x78, 0xda, 0x4b, 0x4b, 0x2c, 0x2e, 0xd1, 0x51, 0x48, 0x83, 0x93, 0x5c, 0x00, 0x35, 0x50, 0x05, 0xad,
+    ];
+    // with the file name a.txt in the header
+    const GZIP_NAMED: [u8; 32] = [
+        0x1f, 0x8b, 0x08, 0x08, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x61, 0x2e, 0x74, 0x78, 0x74, 0x00, 0x0b, 0x2a,
+        0x2d, 0x2e, 0xb1, 0xe2, 0x02, 0x00, 0x79, 0x9f, 0xb8, 0x2e, 0x06, 0x00, 0x00, 0x00,
+    ];
+    const GZIP: [u8; 31] = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0xff, 0x0b, 0xc8, 0x4c, 0xce, 0x56, 0x28, 0xc9, 0x28,
+        0x4a, 0x4d, 0xe5, 0x02, 0x00, 0x19, 0x1f, 0x9d, 0x57, 0x0b, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(Some(Format::Gzip), detect(&GZIP));
+        assert_eq!(Some(Format::Zlib), detect(&FIXED));
+        assert_eq!(None, detect(b"x^2 + y^2"));
+        assert_eq!(None, detect(b""));
+    }
+
+    #[test]
+    fn block_types() {
+        assert_eq!("safe, fast, productive.\n", decompress(&STORED).unwrap());
+        // "fast, " is repeated with a back-reference that overlaps itself
+        assert_eq!("fast, fast, fast\n", decompress(&FIXED).unwrap());
+        // poem.txt is long enough for the compressor to send its own codes
+        assert_eq!(include_str!("../poem.txt"), decompress(include_bytes!("../poem.txt.gz")).unwrap());
+    }
+
+    #[test]
+    fn gzip_members() {
+        assert_eq!("Rust:\n", decompress(&GZIP_NAMED).unwrap());
+        let both = [&GZIP_NAMED[..], &GZIP[..]].concat();
+        assert_eq!("Rust:\nPick three\n", decompress(&both).unwrap());
+    }
+
+    #[test]
+    fn corrupt_data() {
+        let mut bad_checksum = GZIP;
+        bad_checksum[24] ^= 1;
+        assert_eq!(ErrorKind::InvalidData, decompress(&bad_checksum).unwrap_err().kind());
+        assert_eq!(ErrorKind::UnexpectedEof, decompress(&GZIP[..20]).unwrap_err().kind());
+        let mut bad_block = FIXED;
+        // block type 3 doesn't exist
+        bad_block[2] |= 0b110;
+        assert_eq!(ErrorKind::InvalidData, decompress(&bad_block).unwrap_err().kind());
+    }
+}
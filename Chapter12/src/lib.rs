@@ -1,46 +1,975 @@
-use std::{fs, env};
-use std::error::Error;
+use std::borrow::Cow;
+use std::collections::{BinaryHeap, HashMap};
+use std::{env, fmt, str};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, ErrorKind, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub mod aho_corasick;
+pub mod args;
+pub mod casefold;
+pub mod color;
+pub mod encoding;
+pub mod error;
+pub mod glob;
+pub mod ignore;
+pub mod inflate;
+pub mod json;
+pub mod matcher;
+pub mod output;
+pub mod parallel;
+pub mod rcfile;
+pub mod regex;
+pub mod replace;
+pub mod walk;
+pub mod watch;
+
+use args::{Arg, ArgError, ArgParser};
+use casefold::CaseFolder;
+use color::{ColorChoice, Colors};
+use encoding::{Bom, Utf16Decoder};
+use error::MinigrepError;
+use matcher::Matcher;
+use output::{OutputMode, PrintOptions, Sink, Stats};
+use parallel::Piece;
+use rcfile::RcFile;
+use regex::Regex;
+use replace::Replacement;
+use walk::FileFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchMode {
+    // plain substring search, the fast default
+    Literal,
+    // query is a regular expression handled by the regex module
+    Regex,
+    // --fuzzy: lines within this edit distance of the query, see FuzzyMatcher
+    Fuzzy(usize),
+}
 
 pub struct Config {
-    pub query: String,
-    pub file_path: String,
+    // any line matching one of these is selected
+    pub patterns: Vec<String>,
+    // files or directories to search, `-` is stdin
+    pub file_paths: Vec<String>,
     pub ignore_case: bool,
+    // Turkish and Azeri case rules for ignore_case: I pairs with ı and İ with i
+    pub turkic_case: bool,
+    pub mode: SearchMode,
+    // only hits that are whole words (-w) or whole lines (-x) count
+    pub whole_word: bool,
+    pub whole_line: bool,
+    // globs applied when searching a directory
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // --hidden searches dotfiles, --no-ignore searches what .gitignore and .ignore files exclude
+    pub hidden: bool,
+    pub no_ignore: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    pub invert_match: bool,
+    // --lossy: invalid UTF-8 is searched as U+FFFD instead of making the file binary
+    pub lossy: bool,
+    // -m: stop reading a file after this many selected lines
+    pub max_count: Option<usize>,
+    pub output: OutputMode,
+    pub color: ColorChoice,
+    // SGR codes for the colored parts, see color::Colors
+    pub colors: String,
+    // --replace: text to print in place of each hit, see replace::Replacement
+    pub replace: Option<String>,
+    // --write: apply the replacement to the files themselves
+    pub write: bool,
+    // --watch: keep polling the files this often and print the matches in what's appended
+    pub watch: Option<Duration>,
 }
 
 
 impl Config {
     // impl Iterator implies that args can be of any type that implements the Iterator type
     // and returns string items
-    pub fn new(mut args: impl Iterator<Item = String>) -> Result<Config, &'static str> {
+    pub fn new(args: impl Iterator<Item = String>) -> Result<Config, ArgError> {
+        let lookup = |key: &str| env::var(key).ok();
+        let rc = RcFile::find(&env::current_dir().unwrap_or_default(), lookup)?;
+        Config::from_sources(args, &rc, lookup)
+    }
+
+    // The environment lookup is passed in so that tests don't depend on the real environment.
+    // Environment variables only provide defaults, flags on the command line always win.
+    pub fn from_args(
+        args: impl Iterator<Item = String>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, ArgError> {
+        Config::from_sources(args, &RcFile::default(), env)
+    }
+
+    // Each setting comes from the first of these that has it:
+    //   the command line, the environment, the .minigreprc file, the built-in default
+    pub fn from_sources(
+        mut args: impl Iterator<Item = String>,
+        rc: &RcFile,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Config, ArgError> {
         args.next(); // ignoring first value which is name of program
 
-        let query = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Did not get query string"),
+        let mut config = Config {
+            patterns: Vec::new(),
+            file_paths: Vec::new(),
+            ignore_case: false,
+            turkic_case: is_turkic_locale(&env),
+            mode: SearchMode::Literal,
+            whole_word: false,
+            whole_line: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            hidden: false,
+            no_ignore: false,
+            line_number: false,
+            byte_offset: false,
+            before_context: 0,
+            after_context: 0,
+            invert_match: false,
+            lossy: false,
+            max_count: None,
+            output: OutputMode::Lines,
+            color: ColorChoice::Auto,
+            colors: String::new(),
+            replace: None,
+            write: false,
+            watch: None,
+        };
+        config.apply_rc(rc)?;
+        if env("IGNORE_CASE").is_some() {
+            config.ignore_case = true;
+        }
+        if env("USE_REGEX").is_some() {
+            config.mode = SearchMode::Regex;
+        }
+        // comma separated lists, e.g. INCLUDE_GLOBS="*.rs,*.toml"
+        if let Some(globs) = env("INCLUDE_GLOBS") {
+            config.include = glob_list(&globs);
+        }
+        if let Some(globs) = env("EXCLUDE_GLOBS") {
+            config.exclude = glob_list(&globs);
+        }
+        if let Some(colors) = env("MINIGREP_COLORS") {
+            config.colors = colors;
+        }
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        // -A and -B take precedence over -C whatever the order
+        let mut context = None;
+        let mut before_context = None;
+        let mut after_context = None;
+        let mut positionals = Vec::new();
+        // patterns from -e and -f, when there are any every positional is a path
+        let mut patterns = None;
+
+        let mut parser = ArgParser::new(args);
+        while let Some(arg) = parser.next_arg() {
+            let name = match &arg {
+                Arg::Positional(value) => {
+                    positionals.push(value.clone());
+                    continue;
+                }
+                Arg::Short(c) => short_flag(*c).ok_or_else(|| ArgError::UnknownFlag(arg.display()))?,
+                Arg::Long(name) => name.as_str(),
+            };
+            match name {
+                "regexp" => patterns.get_or_insert_with(Vec::new).push(parser.value(&arg)?),
+                "file" => {
+                    let path = parser.value(&arg)?;
+                    patterns.get_or_insert_with(Vec::new).extend(read_patterns(&path)?);
+                }
+                "include" => include.push(parser.value(&arg)?),
+                "exclude" => exclude.push(parser.value(&arg)?),
+                "after-context" => after_context = Some(number_value(&mut parser, &arg)?),
+                "before-context" => before_context = Some(number_value(&mut parser, &arg)?),
+                "context" => context = Some(number_value(&mut parser, &arg)?),
+                "max-count" => config.max_count = Some(number_value(&mut parser, &arg)?),
+                "no-max-count" => {
+                    parser.no_value(&arg)?;
+                    config.max_count = None;
+                }
+                "replace" => config.replace = Some(parser.value(&arg)?),
+                // --fuzzy on its own allows one edit, --fuzzy=2 two
+                "fuzzy" => {
+                    let distance = match parser.optional_value() {
+                        Some(value) => value.parse().map_err(|_| ArgError::InvalidValue {
+                            flag: arg.display(),
+                            value,
+                        })?,
+                        None => 1,
+                    };
+                    config.mode = SearchMode::Fuzzy(distance);
+                }
+                // in seconds, --watch=0.2 polls five times a second
+                "watch" => {
+                    let seconds = parser.optional_value().map_or(Ok(1.0), |value| {
+                        value
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|s| s.is_finite() && *s > 0.0)
+                            .ok_or(ArgError::InvalidValue { flag: arg.display(), value })
+                    })?;
+                    config.watch = Some(Duration::from_secs_f64(seconds));
+                }
+                // --color on its own means auto, like grep
+                "color" | "colour" => {
+                    let value = parser.optional_value().unwrap_or_else(|| "auto".to_string());
+                    config.color = ColorChoice::parse(&value).map_err(|_| ArgError::InvalidValue {
+                        flag: arg.display(),
+                        value,
+                    })?;
+                }
+                _ => {
+                    // the name is checked before the value, --bogus=x is an unknown flag.
+                    // The --no- forms turn off what a .minigreprc file turned on.
+                    let set: fn(&mut Config) = match name {
+                        "ignore-case" => |config| config.ignore_case = true,
+                        "no-ignore-case" => |config| config.ignore_case = false,
+                        "regex" => |config| config.mode = SearchMode::Regex,
+                        "fixed-strings" => |config| config.mode = SearchMode::Literal,
+                        "word-regexp" => |config| config.whole_word = true,
+                        "no-word-regexp" => |config| config.whole_word = false,
+                        "line-regexp" => |config| config.whole_line = true,
+                        "no-line-regexp" => |config| config.whole_line = false,
+                        "line-number" => |config| config.line_number = true,
+                        "no-line-number" => |config| config.line_number = false,
+                        "byte-offset" => |config| config.byte_offset = true,
+                        "no-byte-offset" => |config| config.byte_offset = false,
+                        "invert-match" => |config| config.invert_match = true,
+                        "lossy" => |config| config.lossy = true,
+                        "no-lossy" => |config| config.lossy = false,
+                        "count" => |config| config.output = OutputMode::Count,
+                        "files-with-matches" => |config| config.output = OutputMode::FilesWithMatches,
+                        "files-without-match" => |config| config.output = OutputMode::FilesWithoutMatch,
+                        "json" => |config| config.output = OutputMode::Json,
+                        "hidden" => |config| config.hidden = true,
+                        "no-hidden" => |config| config.hidden = false,
+                        "no-ignore" => |config| config.no_ignore = true,
+                        // the opposite of --no-ignore: follow .gitignore and .ignore files again
+                        "ignore" => |config| config.no_ignore = false,
+                        "write" => |config| config.write = true,
+                        _ => return Err(ArgError::UnknownFlag(arg.display())),
+                    };
+                    parser.no_value(&arg)?;
+                    set(&mut config);
+                }
+            }
+        }
+
+        if !include.is_empty() {
+            config.include = include;
+        }
+        if !exclude.is_empty() {
+            config.exclude = exclude;
+        }
+        if config.write && config.replace.is_none() {
+            return Err(ArgError::Requires {
+                flag: "--write".to_string(),
+                needs: "--replace".to_string(),
+            });
+        }
+        // --lossy lines have U+FFFD in place of the bytes that aren't UTF-8,
+        // writing them back would lose those bytes
+        if config.write && config.lossy {
+            return Err(ArgError::Conflicts {
+                flag: "--write".to_string(),
+                with: "--lossy".to_string(),
+            });
+        }
+        if config.watch.is_some() {
+            config.check_watch()?;
+        }
+        config.before_context = before_context.or(context).unwrap_or(config.before_context);
+        config.after_context = after_context.or(context).unwrap_or(config.after_context);
+
+        let mut positionals = positionals.into_iter();
+        config.patterns = match patterns {
+            Some(patterns) => patterns,
+            None => vec![positionals.next().ok_or(ArgError::MissingQuery)?],
         };
+        config.file_paths = positionals.collect();
+        if config.file_paths.is_empty() {
+            return Err(ArgError::MissingFilePath);
+        }
+        if config.watch.is_some() && config.file_paths.iter().any(|path| path == "-") {
+            return Err(ArgError::Conflicts {
+                flag: "--watch".to_string(),
+                with: "-".to_string(),
+            });
+        }
+        Ok(config)
+    }
 
-        let file_path = match args.next() {
-            Some(arg) => arg,
-            None => return Err("Did not get file path string"),
+    // --watch prints lines as they're appended, which the modes that
+    // summarize whole files can't do, and it never rewrites anything
+    fn check_watch(&self) -> Result<(), ArgError> {
+        let conflict = match self.output {
+            OutputMode::Count => Some("--count"),
+            OutputMode::FilesWithMatches => Some("--files-with-matches"),
+            OutputMode::FilesWithoutMatch => Some("--files-without-match"),
+            _ if self.write => Some("--write"),
+            _ => None,
         };
+        match conflict {
+            Some(with) => Err(ArgError::Conflicts {
+                flag: "--watch".to_string(),
+                with: with.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn apply_rc(&mut self, rc: &RcFile) -> Result<(), ArgError> {
+        for setting in rc.settings() {
+            let invalid = |reason: String| rc.error(setting.line, reason);
+            let flag = || setting.flag().map_err(invalid);
+            let number = || setting.number().map_err(invalid);
+            match setting.key.as_str() {
+                "ignore-case" => self.ignore_case = flag()?,
+                "regex" => self.mode = if flag()? { SearchMode::Regex } else { SearchMode::Literal },
+                "word-regexp" => self.whole_word = flag()?,
+                "line-regexp" => self.whole_line = flag()?,
+                "line-number" => self.line_number = flag()?,
+                "byte-offset" => self.byte_offset = flag()?,
+                "hidden" => self.hidden = flag()?,
+                "no-ignore" => self.no_ignore = flag()?,
+                "lossy" => self.lossy = flag()?,
+                "include" => self.include = setting.list(),
+                "exclude" => self.exclude = setting.list(),
+                "context" => {
+                    self.before_context = number()?;
+                    self.after_context = self.before_context;
+                }
+                "before-context" => self.before_context = number()?,
+                "after-context" => self.after_context = number()?,
+                "max-count" => self.max_count = Some(number()?),
+                "color" | "colour" => {
+                    self.color = ColorChoice::parse(setting.text())
+                        .map_err(|_| invalid(format!("unknown color choice '{}'", setting.text())))?
+                }
+                "colors" => self.colors = setting.text().to_string(),
+                key => return Err(invalid(format!("unknown setting '{key}'"))),
+            }
+        }
+        Ok(())
+    }
 
-        let ignore_case = env::var("IGNORE_CASE").is_ok();
-        Ok(Config{query, file_path, ignore_case})
+    // --fuzzy prints the closest lines first instead of in file order. Only the
+    // modes that print lines care, and with -v there's no distance to go by.
+    fn ranked(&self) -> bool {
+        matches!(self.mode, SearchMode::Fuzzy(_))
+            && !self.invert_match
+            && matches!(self.output, OutputMode::Lines | OutputMode::Json)
+    }
+
+    // Capture references like $1 only mean something in regex mode
+    pub fn replacement(&self) -> Option<Replacement> {
+        let replace = self.replace.as_ref()?;
+        Some(Replacement::new(replace, self.mode == SearchMode::Regex))
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>>{
-    let content = fs::read_to_string(config.file_path)?;
-    let results = if config.ignore_case {
-        search_case_insensitive(&config.query, &content)
+// Long name of each short flag
+fn short_flag(c: char) -> Option<&'static str> {
+    match c {
+        'e' => Some("regexp"),
+        'f' => Some("file"),
+        'i' => Some("ignore-case"),
+        'E' => Some("regex"),
+        'F' => Some("fixed-strings"),
+        'w' => Some("word-regexp"),
+        'x' => Some("line-regexp"),
+        'n' => Some("line-number"),
+        'b' => Some("byte-offset"),
+        'A' => Some("after-context"),
+        'B' => Some("before-context"),
+        'C' => Some("context"),
+        'v' => Some("invert-match"),
+        'm' => Some("max-count"),
+        'c' => Some("count"),
+        'l' => Some("files-with-matches"),
+        'L' => Some("files-without-match"),
+        _ => None,
+    }
+}
+
+// One pattern per line. An empty file gives no patterns, so nothing matches.
+fn read_patterns(path: &str) -> Result<Vec<String>, ArgError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| ArgError::PatternFile {
+        path: path.to_string(),
+        reason: e.to_string(),
+    })?;
+    Ok(contents.lines().map(|line| line.to_string()).collect())
+}
+
+// The locale comes from the first of LC_ALL, LC_CTYPE and LANG that is set, e.g. LANG=tr_TR.UTF-8
+fn is_turkic_locale(env: impl Fn(&str) -> Option<String>) -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .find_map(|key| env(key).filter(|value| !value.is_empty()))
+        .unwrap_or_default();
+    locale.starts_with("tr") || locale.starts_with("az")
+}
+
+fn number_value<I: Iterator<Item = String>>(parser: &mut ArgParser<I>, flag: &Arg) -> Result<usize, ArgError> {
+    let value = parser.value(flag)?;
+    value.parse().map_err(|_| ArgError::InvalidValue {
+        flag: flag.display(),
+        value,
+    })
+}
+
+fn glob_list(value: &str) -> Vec<String> {
+    value.split(',').filter(|g| !g.is_empty()).map(String::from).collect()
+}
+
+pub fn run(config: Config) -> Result<(), MinigrepError> {
+    if let Some(interval) = config.watch {
+        return watch::watch(&config, interval);
+    }
+    // compile the pattern once, not once per file
+    let matcher = Matcher::new(&config)?;
+    let (inputs, unreadable) = collect_inputs(&config)?;
+    let options = print_options(&config, &inputs);
+    // grep puts a separator between the context groups of different files too
+    let has_context = options.before_context > 0 || options.after_context > 0;
+    let separator = (config.output == OutputMode::Lines && has_context).then(|| match &options.colors {
+        Some(colors) => color::paint(&colors.separator, "--"),
+        None => "--".to_string(),
+    });
+    let mut out = FileOutput::new(io::stdout().lock(), separator);
+    let mut totals = Stats::default();
+    let mut failures = Failures::default();
+    let unreadable_count = unreadable.len();
+    for e in unreadable {
+        failures.add(e);
+    }
+
+    // A file that can't be searched is reported and the others are searched
+    // anyway, like grep does. Only failing to write the output stops the run.
+    if inputs.len() == 1 || inputs.iter().any(|input| input.path.as_os_str() == "-") {
+        // Searched right here, straight into stdout. Standard input may be a
+        // pipe that's still being written to, its matches shouldn't wait.
+        for input in &inputs {
+            out.next_file();
+            match search_input(&config, &matcher, options.clone(), input, &mut out) {
+                Ok(stats) => totals.add(&stats),
+                Err(e) if out.broken => return Err(MinigrepError::Output(e)),
+                Err(e) => failures.add(MinigrepError::io(&input.path, e)),
+            }
+        }
     } else {
-        search(&config.query, &content)
+        // Each file is searched on a worker thread, and the output comes out
+        // in input order so it's deterministic, see parallel::for_each_streamed
+        let mut result = Ok(());
+        parallel::for_each_streamed(
+            &inputs,
+            parallel::worker_count(),
+            |input, file_out| {
+                search_input(&config, &matcher, options.clone(), input, file_out)
+                    .map_err(|source| MinigrepError::io(&input.path, source))
+            },
+            |piece| match piece {
+                Piece::Output(chunk) => {
+                    result = out.write_all(&chunk).map_err(MinigrepError::Output);
+                    result.is_ok()
+                }
+                Piece::Done(searched) => {
+                    match searched {
+                        Ok(stats) => totals.add(&stats),
+                        Err(e) => failures.add(e),
+                    }
+                    out.next_file();
+                    true
+                }
+            },
+        );
+        result?;
+    }
+
+    if config.output == OutputMode::Json {
+        output::write_json_summary(&mut out.out, &totals).map_err(MinigrepError::Output)?;
+    }
+    failures.into_result(inputs.len() + unreadable_count)
+}
+
+// The output of one file after the other, with grep's `--` line between
+// files that have context
+struct FileOutput<W: Write> {
+    out: W,
+    separator: Option<String>,
+    wrote_any: bool,
+    wrote_file: bool,
+    // writing failed, so an error from a search is about the output, not the file
+    broken: bool,
+}
+
+impl<W: Write> FileOutput<W> {
+    fn new(out: W, separator: Option<String>) -> FileOutput<W> {
+        FileOutput {
+            out,
+            separator,
+            wrote_any: false,
+            wrote_file: false,
+            broken: false,
+        }
+    }
+
+    fn next_file(&mut self) {
+        self.wrote_file = false;
+    }
+}
+
+impl<W: Write> Write for FileOutput<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if !self.wrote_file && !data.is_empty() {
+            if let Some(separator) = self.separator.as_ref().filter(|_| self.wrote_any) {
+                writeln!(self.out, "{separator}").inspect_err(|_| self.broken = true)?;
+            }
+            self.wrote_file = true;
+            self.wrote_any = true;
+        }
+        self.out.write(data).inspect_err(|_| self.broken = true)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush().inspect_err(|_| self.broken = true)
+    }
+}
+
+// The inputs that couldn't be searched. Each one is reported as it happens,
+// the run then fails with the worst of them.
+#[derive(Default)]
+struct Failures {
+    count: usize,
+    worst: Option<MinigrepError>,
+}
+
+impl Failures {
+    fn add(&mut self, e: MinigrepError) {
+        eprintln!("minigrep: {e}");
+        self.count += 1;
+        if self.worst.as_ref().is_none_or(|worst| e.exit_code() > worst.exit_code()) {
+            self.worst = Some(e);
+        }
+    }
+
+    fn into_result(self, inputs: usize) -> Result<(), MinigrepError> {
+        match self.worst {
+            None => Ok(()),
+            Some(worst) => Err(MinigrepError::Incomplete {
+                failed: self.count,
+                inputs,
+                worst: Box::new(worst),
+            }),
+        }
+    }
+}
+
+fn print_options(config: &Config, inputs: &[Input]) -> PrintOptions {
+    let colors = config
+        .color
+        .enabled(io::stdout().is_terminal())
+        .then(|| Colors::parse(&config.colors));
+    // context makes no sense once the lines are out of order
+    let (before_context, after_context) = if config.ranked() {
+        (0, 0)
+    } else {
+        (config.before_context, config.after_context)
+    };
+    PrintOptions {
+        // every hit is prefixed with the file it came from unless there's only one file
+        with_path: config.file_paths.len() > 1 || inputs.iter().any(|input| input.walked),
+        line_number: config.line_number,
+        byte_offset: config.byte_offset,
+        before_context,
+        after_context,
+        colors,
+        replacement: config.replacement(),
+    }
+}
+
+// A file to search. Files found by walking a directory are skipped if they
+// look binary, files named on the command line are searched and reported as
+// binary if they match.
+struct Input {
+    path: PathBuf,
+    walked: bool,
+}
+
+// Expands the paths from the command line into the list of files to search, in
+// order, along with the directories that couldn't be read while walking
+fn collect_inputs(config: &Config) -> Result<(Vec<Input>, Vec<MinigrepError>), MinigrepError> {
+    let filter = FileFilter::new(&config.include, &config.exclude)?
+        .hidden(config.hidden)
+        .ignore_files(!config.no_ignore);
+    let mut inputs = Vec::new();
+    let mut errors = Vec::new();
+    for file_path in &config.file_paths {
+        let path = Path::new(file_path);
+        if file_path != "-" && path.is_dir() {
+            let walked = walk::walk(path, &filter);
+            inputs.extend(walked.files.into_iter().map(|file| Input { path: file, walked: true }));
+            errors.extend(walked.errors.into_iter().map(|(path, source)| MinigrepError::io(path, source)));
+        } else {
+            inputs.push(Input { path: path.to_path_buf(), walked: false });
+        }
+    }
+    Ok((inputs, errors))
+}
+
+// Writes the output for the file to out and returns its stats.
+// Skipped files write nothing and count as no search at all.
+fn search_input(
+    config: &Config,
+    matcher: &Matcher,
+    options: PrintOptions,
+    input: &Input,
+    out: &mut dyn Write,
+) -> io::Result<Stats> {
+    let mut printer = output::new_sink(config.output, options, matcher, out);
+    // with --write the selected lines are noted on the way to the preview
+    let mut selection = replace::Selection::new(printer.as_mut(), config.write);
+    let sink = &mut selection;
+
+    let stats = if input.path.as_os_str() == "-" {
+        if config.write {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "--write can't rewrite standard input"));
+        }
+        let (reader, _) = decompressed(io::stdin().lock())?;
+        let (reader, _) = decoded(reader)?;
+        search_lines(config, matcher, "(standard input)", reader, sink)?
+    } else {
+        let label = input.path.display().to_string();
+        let mut compressed = false;
+        let result = (|| {
+            let mut file = BufReader::with_capacity(READ_BUFFER_SIZE, File::open(&input.path)?);
+            // known before decompressing, which can fail on the header already
+            compressed = inflate::detect(file.fill_buf()?).is_some();
+            let (reader, _) = decompressed(file)?;
+            if compressed && config.write {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("--write can't rewrite compressed file {label}"),
+                ));
+            }
+            let (mut reader, utf16) = decoded(reader)?;
+            if utf16 && config.write {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("--write can't rewrite UTF-16 file {label}"),
+                ));
+            }
+            // for compressed files it's the decompressed data that has to look like text
+            if input.walked && is_binary(reader.fill_buf()?) {
+                return Ok(Stats::default());
+            }
+            search_lines(config, matcher, &label, reader, &mut *sink)
+        })();
+        match result {
+            // like a binary file, a corrupt compressed file found by walking a
+            // directory is skipped, with a warning as it's likely a mistake
+            Err(e) if compressed && input.walked && matches!(e.kind(), ErrorKind::InvalidData | ErrorKind::UnexpectedEof) => {
+                eprintln!("minigrep: {label}: {e}, skipped");
+                return Ok(Stats::default());
+            }
+            result => result?,
+        }
+    };
+    // the printed lines preview the change, --write then makes it to the
+    // same lines. With -v the selected lines have no hits, so nothing changes.
+    let selected = selection.lines;
+    drop(printer);
+    if let Some(replacement) = config.replacement().filter(|_| config.write) {
+        match replace::rewrite_file(&input.path, matcher, &replacement, &selected) {
+            Err(e) if e.kind() == ErrorKind::InvalidData => {
+                eprintln!("minigrep: {}: not valid UTF-8, left unchanged", input.path.display());
+            }
+            Err(e) if e.kind() == ErrorKind::InvalidInput => {
+                eprintln!("minigrep: {}: binary file, left unchanged", input.path.display());
+            }
+            result => {
+                result?;
+            }
+        }
+    }
+    Ok(stats)
+}
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+// Reads gzip and zlib data decompressed, anything else as it is.
+// Also says whether the data was compressed.
+fn decompressed<'r>(mut reader: impl BufRead + 'r) -> io::Result<(Box<dyn BufRead + 'r>, bool)> {
+    match inflate::detect(reader.fill_buf()?) {
+        Some(format) => {
+            let decoder = inflate::Decoder::new(reader, format)?;
+            Ok((Box::new(BufReader::with_capacity(READ_BUFFER_SIZE, decoder)), true))
+        }
+        None => Ok((Box::new(reader), false)),
+    }
+}
+
+fn search_lines(
+    config: &Config,
+    matcher: &Matcher,
+    path: &str,
+    reader: impl BufRead,
+    sink: &mut dyn Sink,
+) -> io::Result<Stats> {
+    if config.ranked() {
+        search_ranked(matcher, config.max_count, config.lossy, path, reader, sink)
+    } else {
+        search_reader(matcher, config.invert_match, config.max_count, config.lossy, path, reader, sink)
+    }
+}
+
+// UTF-16 with a byte order mark is read as UTF-8, the byte order mark of
+// UTF-8 itself is dropped. Also says whether the data was UTF-16.
+fn decoded<'r>(mut reader: Box<dyn BufRead + 'r>) -> io::Result<(Box<dyn BufRead + 'r>, bool)> {
+    let bom = encoding::detect(reader.fill_buf()?);
+    if let Some(bom) = bom {
+        reader.consume(bom.size());
+    }
+    match bom {
+        Some(Bom::Utf16Le) | Some(Bom::Utf16Be) => {
+            let decoder = Utf16Decoder::new(reader, bom == Some(Bom::Utf16Be));
+            Ok((Box::new(BufReader::with_capacity(READ_BUFFER_SIZE, decoder)), true))
+        }
+        _ => Ok((reader, false)),
+    }
+}
+
+// Feeds every line of the input to the sink. With invert the lines that
+// don't match are the selected ones. With max_count reading stops at the
+// selected line that reaches it, the rest of the input isn't even read.
+// Lines are read one at a time into a single reused buffer, so memory use
+// depends on the longest line rather than on the size of the input.
+// Input with a NUL byte near the start is binary, and so is the rest of the
+// input from the first line that isn't valid UTF-8 on, unless lossy is set.
+// The sink is told, so it can say that the file matches instead of printing
+// the lines.
+pub fn search_reader<R: BufRead>(
+    matcher: &Matcher,
+    invert: bool,
+    max_count: Option<usize>,
+    lossy: bool,
+    path: &str,
+    mut reader: R,
+    sink: &mut dyn Sink,
+) -> io::Result<Stats> {
+    sink.begin_file(path)?;
+    let mut stats = Stats {
+        searches: 1,
+        ..Stats::default()
     };
+    let mut binary = is_binary(reader.fill_buf()?);
+    if binary {
+        sink.binary();
+    }
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    while sink.wants_more() && max_count.is_none_or(|max| stats.matched_lines < max as u64) {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+        let line = decode_line(trim_line_ending(&buf), lossy, &mut binary, sink);
+        let selected = matcher.is_match(&line) != invert;
+        sink.line(line_number, stats.bytes_searched as usize, &line, selected)?;
+        stats.bytes_searched += read as u64;
+        stats.matched_lines += selected as u64;
+    }
+    stats.searches_with_match = (stats.matched_lines > 0) as u64;
+    sink.end_file()?;
+    Ok(stats)
+}
 
-    for result in results {
-        println!("{result}");
+// Like search_reader, but only the selected lines are fed to the sink, the
+// closest first as measured by Matcher::distance. Nothing can be fed until the
+// whole input has been read, so the selected lines are kept until then. With
+// max_count only that many are kept, in a heap with the worst of them on top.
+pub fn search_ranked<R: BufRead>(
+    matcher: &Matcher,
+    max_count: Option<usize>,
+    lossy: bool,
+    path: &str,
+    mut reader: R,
+    sink: &mut dyn Sink,
+) -> io::Result<Stats> {
+    sink.begin_file(path)?;
+    let mut stats = Stats {
+        searches: 1,
+        ..Stats::default()
+    };
+    let mut binary = is_binary(reader.fill_buf()?);
+    if binary {
+        sink.binary();
+    }
+    let max_count = max_count.unwrap_or(usize::MAX);
+    // ordered by distance, then line number, so equally close lines stay in file order
+    let mut selected: BinaryHeap<(usize, usize, usize, String)> = BinaryHeap::new();
+    let mut buf = Vec::new();
+    let mut line_number = 0;
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break;
+        }
+        line_number += 1;
+        let line = decode_line(trim_line_ending(&buf), lossy, &mut binary, sink);
+        if let Some(distance) = matcher.distance(&line) {
+            // a line no closer than the worst kept one comes after it, so it's out
+            let full = selected.len() == max_count;
+            if !full || selected.peek().is_some_and(|&(worst, ..)| distance < worst) {
+                selected.push((distance, line_number, stats.bytes_searched as usize, line.to_string()));
+                if full {
+                    selected.pop();
+                }
+            }
+        }
+        stats.bytes_searched += read as u64;
     }
-    Ok(())
+    for (_, line_number, byte_offset, line) in &selected.into_sorted_vec() {
+        if !sink.wants_more() {
+            break;
+        }
+        sink.line(*line_number, *byte_offset, line, true)?;
+        stats.matched_lines += 1;
+    }
+    stats.searches_with_match = (stats.matched_lines > 0) as u64;
+    sink.end_file()?;
+    Ok(stats)
+}
+
+// The line as text, with anything that isn't valid UTF-8 replaced by U+FFFD.
+// Unless lossy is set that makes the input binary from here on.
+fn decode_line<'b>(bytes: &'b [u8], lossy: bool, binary: &mut bool, sink: &mut dyn Sink) -> Cow<'b, str> {
+    match str::from_utf8(bytes) {
+        Ok(line) => Cow::Borrowed(line),
+        Err(_) => {
+            if !lossy && !*binary {
+                *binary = true;
+                sink.binary();
+            }
+            String::from_utf8_lossy(bytes)
+        }
+    }
+}
+
+// Strips "\n" or "\r\n", like str::lines does
+fn trim_line_ending(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+// Where a hit was found: the line it's on and its position in the contents
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    // starts at 1
+    pub line_number: usize,
+    // offset of the start of the line from the start of the contents
+    pub byte_offset: usize,
+    pub line: &'a str,
+    // byte span of the first hit within line
+    pub span: (usize, usize),
+}
+
+// Lazily finds the matching lines of contents. Each line is only searched when
+// the next match is asked for, so taking a few matches doesn't search the rest
+// and nothing is collected unless the caller collects it:
+//   let first_three: Vec<Match> = Searcher::new(&matcher, contents).take(3).collect();
+pub struct Searcher<'m, 'a> {
+    matcher: &'m Matcher,
+    lines: NumberedLines<'a>,
+}
+
+impl<'m, 'a> Searcher<'m, 'a> {
+    pub fn new(matcher: &'m Matcher, contents: &'a str) -> Searcher<'m, 'a> {
+        Searcher {
+            matcher,
+            lines: NumberedLines::new(contents),
+        }
+    }
+}
+
+impl<'a> Iterator for Searcher<'_, 'a> {
+    type Item = Match<'a>;
+
+    fn next(&mut self) -> Option<Match<'a>> {
+        self.lines.find_map(|(line_number, byte_offset, line)| {
+            self.matcher.find(line).map(|span| Match {
+                line_number,
+                byte_offset,
+                line,
+                span,
+            })
+        })
+    }
+}
+
+// Like search, but keeps the position of every matching line
+pub fn search_matches<'a>(matcher: &Matcher, contents: &'a str) -> Vec<Match<'a>> {
+    Searcher::new(matcher, contents).collect()
+}
+
+// The matching lines with every hit replaced, what --replace prints
+pub fn search_replace(matcher: &Matcher, replacement: &Replacement, contents: &str) -> Vec<String> {
+    Searcher::new(matcher, contents)
+        .map(|m| replacement.replace_all(matcher, m.line).0)
+        .collect()
+}
+
+// Same lines as str::lines, with their line number and byte offset
+struct NumberedLines<'a> {
+    rest: &'a str,
+    line_number: usize,
+    byte_offset: usize,
+}
+
+impl<'a> NumberedLines<'a> {
+    fn new(contents: &'a str) -> NumberedLines<'a> {
+        NumberedLines {
+            rest: contents,
+            line_number: 0,
+            byte_offset: 0,
+        }
+    }
+}
+
+impl<'a> Iterator for NumberedLines<'a> {
+    type Item = (usize, usize, &'a str);
+
+    fn next(&mut self) -> Option<(usize, usize, &'a str)> {
+        if self.rest.is_empty() {
+            return None;
+        }
+        let raw = match self.rest.find('\n') {
+            Some(i) => &self.rest[..=i],
+            None => self.rest,
+        };
+        self.rest = &self.rest[raw.len()..];
+        let line = raw.strip_suffix('\n').unwrap_or(raw);
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let item = (self.line_number + 1, self.byte_offset, line);
+        self.line_number += 1;
+        self.byte_offset += raw.len();
+        Some(item)
+    }
+}
+
+// Same heuristic as grep: a NUL byte near the start means the file isn't text
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes.iter().take(8192).any(|&b| b == 0)
 }
 
 pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
@@ -57,8 +986,14 @@ pub fn search<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
     // }
     // results
 
-    // New implementation using iterators
-    contents.lines().filter(|line| line.contains(query)).collect()
+    // Older implementation using iterators
+
+    // contents.lines().filter(|line| line.contains(query)).collect()
+
+    // New implementation: a thin wrapper over Searcher, which finds the matches lazily.
+    // Callers that don't need all of them can use Searcher directly and stop early.
+    let matcher = Matcher::Literal(query.to_string());
+    Searcher::new(&matcher, contents).map(|m| m.line).collect()
 }
 
 pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a str> {
@@ -73,18 +1008,253 @@ pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<&'a st
     // }
     // results
 
-    // New Implementation using iterators
+    // Older implementation using iterators
 
     // to_lowercase returns owned String because it might need to allocate new memory
     // contains takes &str to avoid taking ownership, hence we pass &query here
     // Rust does dereference coersion to convert &String to &str
-    let query = query.to_lowercase();
-    contents.lines().filter(|line| line.to_lowercase().contains(&query)).collect()
+    // let query = query.to_lowercase();
+    // contents.lines().filter(|line| line.to_lowercase().contains(&query)).collect()
+
+    // New implementation: only the query is folded up front, each line is folded
+    // a character at a time while comparing, so no String is allocated per line.
+    // Full case folding also matches ß with SS, which to_lowercase doesn't.
+    let folder = CaseFolder::new(query, false);
+    contents.lines().filter(|line| folder.is_match(line)).collect()
+}
+
+pub fn search_regex<'a>(re: &Regex, contents: &'a str) -> Vec<&'a str> {
+    contents.lines().filter(|line| re.is_match(line)).collect()
+}
+
+// --fuzzy: approximate matching. A line matches when some part of it can be
+// turned into the query with at most max_distance single character insertions,
+// deletions or substitutions (the Levenshtein distance).
+//
+// This uses Myers' bit-parallel algorithm. Searching the text one character at
+// a time fills in the table of edit distances column by column. Neighbouring
+// cells in a column differ by -1, 0 or +1, so a whole column fits in two bit
+// masks, one bit per query character, and the next column is worked out from the
+// previous one with a handful of bit operations whatever the query's length.
+// The price is that the query can be at most 64 characters, one u64 worth.
+pub struct FuzzyMatcher {
+    len: usize,
+    max_distance: usize,
+    fold: Option<bool>,
+    // where each character appears in the query, and in the query reversed
+    forward: CharMasks,
+    reverse: CharMasks,
+}
+
+pub const MAX_FUZZY_LEN: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FuzzyError {
+    // the length as typed, and once case is folded if that's different
+    TooLong { len: usize, folded: usize },
+}
+
+impl fmt::Display for FuzzyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FuzzyError::TooLong { len, folded } => {
+                write!(f, "fuzzy queries can be at most {MAX_FUZZY_LEN} characters, this one has {len}")?;
+                if folded != len {
+                    write!(f, " ({folded} with case ignored)")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for FuzzyError {}
+
+impl FuzzyMatcher {
+    // fold is Some(turkic) to ignore case, like for AhoCorasick
+    pub fn new(query: &str, max_distance: usize, fold: Option<bool>) -> Result<FuzzyMatcher, FuzzyError> {
+        let chars: Vec<char> = match fold {
+            Some(turkic) => query.chars().flat_map(|c| casefold::fold(c, turkic)).collect(),
+            None => query.chars().collect(),
+        };
+        if chars.len() > MAX_FUZZY_LEN {
+            return Err(FuzzyError::TooLong {
+                len: query.chars().count(),
+                folded: chars.len(),
+            });
+        }
+        Ok(FuzzyMatcher {
+            len: chars.len(),
+            max_distance,
+            fold,
+            forward: CharMasks::new(chars.iter().copied()),
+            reverse: CharMasks::new(chars.iter().rev().copied()),
+        })
+    }
+
+    // The closest hit starting at or after `start`: its byte span and its distance.
+    // Of several equally close hits the one that ends first wins.
+    pub fn find_at(&self, line: &str, start: usize) -> Option<((usize, usize), usize)> {
+        if self.len == 0 {
+            return Some(((start, start), 0));
+        }
+        let chars = self.chars(line, start);
+        let last = 1 << (self.len - 1);
+
+        // Forwards, with a free start: the distance is the best over every
+        // place the hit could start, so this only finds where the best hit ends.
+        let mut column = Column::new(self.len);
+        let mut best: Option<(usize, usize)> = None;
+        for (i, &(c, _, _)) in chars.iter().enumerate() {
+            column.advance(self.forward.get(c), last, false);
+            if column.score <= self.max_distance && best.is_none_or(|(_, d)| column.score < d) {
+                best = Some((i, column.score));
+                if column.score == 0 {
+                    break;
+                }
+            }
+        }
+        let Some((end, distance)) = best else {
+            // deleting the whole query is always an option
+            return (self.len <= self.max_distance).then_some(((start, start), self.len));
+        };
+
+        // Backwards from the end with the reversed query, where the hit has to
+        // start at the end, finds the closest start that gives the same distance
+        let mut column = Column::new(self.len);
+        let mut first = end;
+        for i in (0..=end).rev() {
+            column.advance(self.reverse.get(chars[i].0), last, true);
+            if column.score == distance {
+                first = i;
+                break;
+            }
+        }
+        Some(((chars[first].1, chars[end].2), distance))
+    }
+
+    // The distance of the closest hit in the line
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        self.find_at(line, 0).map(|(_, distance)| distance)
+    }
+
+    // The characters of line from start on, folded if case is ignored, each
+    // with the byte span of the character of line it came from
+    fn chars(&self, line: &str, start: usize) -> Vec<(char, usize, usize)> {
+        let mut chars = Vec::with_capacity(line.len() - start);
+        for (i, c) in line[start..].char_indices() {
+            let span = (start + i, start + i + c.len_utf8());
+            match self.fold {
+                Some(turkic) => chars.extend(casefold::fold(c, turkic).map(|f| (f, span.0, span.1))),
+                None => chars.push((c, span.0, span.1)),
+            }
+        }
+        chars
+    }
+}
+
+// For each character, a mask with a bit set for every position of the query
+// it appears at. ASCII gets a table, anything else a map.
+struct CharMasks {
+    ascii: [u64; 128],
+    other: HashMap<char, u64>,
+}
+
+impl CharMasks {
+    fn new(chars: impl Iterator<Item = char>) -> CharMasks {
+        let mut masks = CharMasks {
+            ascii: [0; 128],
+            other: HashMap::new(),
+        };
+        for (i, c) in chars.enumerate() {
+            match masks.ascii.get_mut(c as usize) {
+                Some(mask) => *mask |= 1 << i,
+                None => *masks.other.entry(c).or_insert(0) |= 1 << i,
+            }
+        }
+        masks
+    }
+
+    fn get(&self, c: char) -> u64 {
+        match self.ascii.get(c as usize) {
+            Some(&mask) => mask,
+            None => self.other.get(&c).copied().unwrap_or(0),
+        }
+    }
+}
+
+// One column of the edit distance table. Bit i of pv (mv) is set when the
+// cell for query character i is one more (one less) than the cell above it.
+// score is the bottom cell, the distance of the whole query.
+struct Column {
+    pv: u64,
+    mv: u64,
+    score: usize,
+}
+
+impl Column {
+    fn new(len: usize) -> Column {
+        Column {
+            pv: !0,
+            mv: 0,
+            score: len,
+        }
+    }
+
+    // Moves on by one text character, eq being its mask in the query.
+    // Unanchored the hit can start anywhere, so the top cell is always 0.
+    // Anchored it has to start at the first character and the top cell counts
+    // the characters seen.
+    fn advance(&mut self, eq: u64, last: u64, anchored: bool) {
+        let xv = eq | self.mv;
+        let xh = ((eq & self.pv).wrapping_add(self.pv) ^ self.pv) | eq;
+        let ph = self.mv | !(xh | self.pv);
+        let mh = self.pv & xh;
+        if ph & last != 0 {
+            self.score += 1;
+        } else if mh & last != 0 {
+            self.score -= 1;
+        }
+        let ph = (ph << 1) | anchored as u64;
+        let mh = mh << 1;
+        self.pv = mh | !(xv | ph);
+        self.mv = ph & xv;
+    }
+}
+
+// A line within the distance of the query
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatch<'a> {
+    pub distance: usize,
+    // span is that of the closest hit
+    pub hit: Match<'a>,
+}
+
+// The lines within the distance of the query, closest first.
+// Lines that are equally close stay in the order they're in.
+pub fn search_fuzzy<'a>(matcher: &FuzzyMatcher, contents: &'a str) -> Vec<FuzzyMatch<'a>> {
+    let mut matches: Vec<FuzzyMatch> = NumberedLines::new(contents)
+        .filter_map(|(line_number, byte_offset, line)| {
+            let (span, distance) = matcher.find_at(line, 0)?;
+            Some(FuzzyMatch {
+                distance,
+                hit: Match {
+                    line_number,
+                    byte_offset,
+                    line,
+                    span,
+                },
+            })
+        })
+        .collect();
+    matches.sort_by_key(|m| m.distance);
+    matches
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::io::Read;
 
     #[test]
     fn case_sensitive() {
@@ -114,4 +1284,828 @@ Trust me.";
             search_case_insensitive(query, contents),
         )
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn whole_word() {
+        let contents = "\
+safe, fast, productive.
+unsafe code
+safe_mode
+fast-safe
+unsafe, but safe";
+
+        assert_eq!(
+            vec!["safe, fast, productive.", "fast-safe", "unsafe, but safe"],
+            matching_lines(&["minigrep", "-w", "safe", "-"], contents),
+        );
+        assert_eq!(vec!["unsafe code", "unsafe, but safe"], matching_lines(&["minigrep", "-wi", "UNSAFE", "-"], contents));
+        // the other alternatives are tried when the first one isn't a whole word
+        assert_eq!(vec!["safe_mode"], matching_lines(&["minigrep", "-wE", "safe_m|safe_mode", "-"], contents));
+        assert_eq!(
+            vec!["safe, fast, productive.", "fast-safe", "unsafe, but safe"],
+            matching_lines(&["minigrep", "-w", "-e", "safe, f", "-e", "safe", "-"], contents),
+        );
+    }
+
+    #[test]
+    fn whole_word_unicode() {
+        let contents = "\
+नमस्ते
+नमस दुनिया
+café au lait
+cafe\u{301}ine
+caféine";
+
+        // the virama after नमस is part of the same word
+        assert_eq!(vec!["नमस दुनिया"], matching_lines(&["minigrep", "-w", "नमस", "-"], contents));
+        assert_eq!(vec!["café au lait"], matching_lines(&["minigrep", "-w", "café", "-"], contents));
+        // an accent written as a combining mark doesn't end the word either
+        assert!(matching_lines(&["minigrep", "-w", "cafe", "-"], contents).is_empty());
+        assert_eq!(vec!["café au lait"], matching_lines(&["minigrep", "-wi", "CAFÉ", "-"], contents));
+    }
+
+    #[test]
+    fn whole_line() {
+        let contents = "\
+Pick three
+Pick three please
+pick three";
+
+        assert_eq!(vec!["Pick three"], matching_lines(&["minigrep", "-x", "Pick three", "-"], contents));
+        assert_eq!(vec!["Pick three", "pick three"], matching_lines(&["minigrep", "-xi", "pick three", "-"], contents));
+        assert_eq!(vec!["Pick three"], matching_lines(&["minigrep", "-xE", "P\\w+ \\w+", "-"], contents));
+        assert_eq!(vec!["Pick three", "pick three"], matching_lines(&["minigrep", "-x", "-e", "Pick", "-e", "pick three", "-e", "Pick three", "-"], contents));
+        // -x wins over -w
+        assert!(matching_lines(&["minigrep", "-wx", "three", "-"], contents).is_empty());
+    }
+
+    #[test]
+    fn case_insensitive_full_folding() {
+        let contents = "\
+Straße
+STRASSE
+strase
+ΣΊΣΥΦΟΣ";
+
+        assert_eq!(vec!["Straße", "STRASSE"], search_case_insensitive("strasse", contents));
+        assert_eq!(vec!["Straße", "STRASSE"], search_case_insensitive("STRAẞE", contents));
+        // final sigma folds to the same letter as the other sigmas
+        assert_eq!(vec!["ΣΊΣΥΦΟΣ"], search_case_insensitive("σίσυφος", contents));
+    }
+
+    #[test]
+    fn case_insensitive_multilingual() {
+        // the strings from Chapter8's strings::text
+        let contents = "\
+Hello, World!
+नमस्ते
+hello again!";
+
+        assert_eq!(vec!["नमस्ते"], search_case_insensitive("नमस्ते", contents));
+        assert_eq!(vec!["Hello, World!", "hello again!"], search_case_insensitive("HELLO", contents));
+
+        let config = parse(&["minigrep", "-i", "नमस्ते", "-"], &[]).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        // all 18 bytes of the word, including the diacritics
+        assert_eq!(Some((0, 18)), matcher.find("नमस्ते"));
+    }
+
+    #[test]
+    fn case_insensitive_turkic() {
+        let contents = "\
+ISTANBUL
+İstanbul
+ıspanak";
+
+        let config = parse(&["minigrep", "-i", "istanbul", "-"], &[("LANG", "tr_TR.UTF-8")]).unwrap();
+        assert!(config.turkic_case);
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec!["İstanbul"], search_matches(&matcher, contents).iter().map(|m| m.line).collect::<Vec<_>>());
+
+        let config = parse(&["minigrep", "-i", "ISPANAK", "-"], &[("LANG", "tr_TR.UTF-8")]).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        assert_eq!(vec!["ıspanak"], search_matches(&matcher, contents).iter().map(|m| m.line).collect::<Vec<_>>());
+
+        // without a Turkish locale dotless ı is its own letter
+        let config = parse(&["minigrep", "-i", "ISPANAK", "-"], &[("LANG", "en_US.UTF-8")]).unwrap();
+        assert!(!config.turkic_case);
+        assert!(search_matches(&Matcher::new(&config).unwrap(), contents).is_empty());
+    }
+
+    #[test]
+    fn regex_structural() {
+        let re = Regex::new(r"fn \w+\(").unwrap();
+        let contents = "\
+pub fn search<'a>(query: &str) {
+let f = fn_name;
+fn run(config: Config) {
+// fn without parens";
+
+        assert_eq!(
+            vec!["fn run(config: Config) {"],
+            search_regex(&re, contents),
+        );
+        // `search<'a>(` doesn't match since `<` is not a word character
+    }
+
+    #[test]
+    fn regex_classes_and_anchors() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three
+Trust me.";
+
+        let re = Regex::new("^[A-Z][a-z]+:$").unwrap();
+        assert_eq!(vec!["Rust:"], search_regex(&re, contents));
+
+        let re = Regex::new(r"[^\w\s]$").unwrap();
+        assert_eq!(vec!["Rust:", "safe, fast, productive.", "Trust me."], search_regex(&re, contents));
+
+        let re = Regex::new(r"\bthree\b").unwrap();
+        assert_eq!(vec!["Pick three"], search_regex(&re, contents));
+    }
+
+    #[test]
+    fn regex_alternation_and_repetition() {
+        let re = Regex::new("(fast|slow), (pro|con)ductive").unwrap();
+        assert_eq!(Some((6, 22)), re.find("safe, fast, productive."));
+
+        let re = Regex::new("^a{2,3}b+c?$").unwrap();
+        assert!(re.is_match("aabbb"));
+        assert!(re.is_match("aaabc"));
+        assert!(!re.is_match("abc"));
+        assert!(!re.is_match("aaaab"));
+
+        // lazy repetition stops at the first possible end
+        let re = Regex::new("<.+?>").unwrap();
+        assert_eq!(Some((0, 3)), re.find("<a><b>"));
+        let re = Regex::new("<.+>").unwrap();
+        assert_eq!(Some((0, 6)), re.find("<a><b>"));
+    }
+
+    #[test]
+    fn regex_case_insensitive() {
+        let re = Regex::with_ignore_case("^t?rust", true).unwrap();
+        let contents = "\
+Rust:
+Safe, fast, productive.
+Pick three
+Trust me.";
+
+        assert_eq!(vec!["Rust:", "Trust me."], search_regex(&re, contents));
+    }
+
+    #[test]
+    fn regex_errors() {
+        use regex::RegexError;
+
+        assert_eq!(Err(RegexError::UnclosedGroup(0)), Regex::new("(abc").map(|_| ()));
+        assert_eq!(Err(RegexError::UnmatchedParen(3)), Regex::new("abc)").map(|_| ()));
+        assert_eq!(Err(RegexError::UnclosedClass(1)), Regex::new("a[bc").map(|_| ()));
+        assert_eq!(Err(RegexError::NothingToRepeat(0)), Regex::new("*a").map(|_| ()));
+        assert_eq!(Err(RegexError::InvalidRange(2)), Regex::new("[z-a]").map(|_| ()));
+        assert_eq!(Err(RegexError::TrailingBackslash(2)), Regex::new("ab\\").map(|_| ()));
+        // an incomplete counted repetition is taken literally
+        assert!(Regex::new("a{1,x}").unwrap().is_match("a{1,x}"));
+        assert_eq!(Err(RegexError::RepeatTooBig(1)), Regex::new("x{100000}").map(|_| ()));
+        assert_eq!(Err(RegexError::RepeatTooBig(1)), Regex::new("x{1,1001}").map(|_| ()));
+        assert_eq!(Err(RegexError::RepeatTooBig(1)), Regex::new("x{99999999999}").map(|_| ()));
+        assert!(Regex::new("x{1000}").is_ok());
+    }
+
+    #[test]
+    fn regex_long_lines() {
+        let line = format!("a{}b", "x".repeat(100_000));
+        assert_eq!(Some((0, line.len())), Regex::new("a.*b").unwrap().find(&line));
+        assert_eq!(Some((0, 2)), Regex::new("a.*?x").unwrap().find(&line));
+        assert_eq!(Some((1, line.len() - 1)), Regex::new("[a-z]x+").unwrap().find(&line[1..]).map(|(s, e)| (s + 1, e + 1)));
+
+        // a capturing group around one character keeps its last iteration
+        let re = Regex::new("(x)*(b)").unwrap();
+        let slots = re.captures_at(&line, 0).unwrap();
+        assert_eq!(Some((line.len() - 2, line.len() - 1)), slots[1]);
+        assert_eq!(Some((1, line.len())), slots[0]);
+        assert_eq!(Some((0, 2)), Regex::new("(?:a|x){2}").unwrap().find(&line));
+
+        let line = "ab".repeat(20_000);
+        assert!(Regex::new("^(?:ab)*$").unwrap().is_match(&line));
+    }
+
+    #[test]
+    fn regex_nested_repetition() {
+        let started = std::time::Instant::now();
+        let line = "a".repeat(30);
+        assert!(!Regex::new("(a|a)*b").unwrap().is_match(&line));
+        assert!(!Regex::new("(a|aa)*c").unwrap().is_match(&line));
+        assert!(!Regex::new("((a|a)*)*b").unwrap().is_match(&line));
+        assert!(!Regex::new("(a*)*b").unwrap().is_match(&line));
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+
+        // the failures remembered are only those of the same repetition
+        let re = Regex::new("(a|ab)(c|bcd)(d*)").unwrap();
+        assert_eq!(Some((0, 4)), re.find("abcd"));
+        let slots = Regex::new("(a|a)*(a)").unwrap().captures_at(&line, 0).unwrap();
+        assert_eq!(Some((28, 29)), slots[1]);
+        assert_eq!(Some((29, 30)), slots[2]);
+    }
+
+    #[test]
+    fn regex_long_alternating_repetition() {
+        // a body that can match more than one way still mustn't take stack per
+        // iteration, the parallel search runs on threads with small stacks
+        let searched = std::thread::Builder::new()
+            .stack_size(64 * 1024)
+            .spawn(|| {
+                let line = "abc".repeat(5000);
+                let plus = Regex::new("(?:a|bc)+").unwrap().find(&line);
+                let slots = Regex::new("(a|bc)*$").unwrap().captures_at(&line, 0).unwrap();
+                (plus, slots)
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(Some((0, 15000)), searched.0);
+        assert_eq!(Some((0, 15000)), searched.1[0]);
+        assert_eq!(Some((14998, 15000)), searched.1[1]);
+        assert_eq!(
+            Err(regex::RegexError::TooLarge),
+            Regex::new("(?:x{1000}){1000}").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(!is_binary("safe, fast, productive.".as_bytes()));
+        assert!(is_binary(&[0x7f, b'E', b'L', b'F', 0, 0, 1]));
+    }
+
+    fn parse(args: &[&str], env: &[(&str, &str)]) -> Result<Config, ArgError> {
+        let args = args.iter().map(|a| a.to_string());
+        Config::from_args(args, |key| {
+            env.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string())
+        })
+    }
+
+    // The lines of contents selected by a matcher built from args
+    fn matching_lines<'a>(args: &[&str], contents: &'a str) -> Vec<&'a str> {
+        let matcher = Matcher::new(&parse(args, &[]).unwrap()).unwrap();
+        search_matches(&matcher, contents).iter().map(|m| m.line).collect()
+    }
+
+    #[test]
+    fn config_flags() {
+        let config = parse(&["minigrep", "-nv", "--count", "--regex", "safe", "poem.txt"], &[]).unwrap();
+        assert_eq!(vec!["safe"], config.patterns);
+        assert_eq!(vec!["poem.txt"], config.file_paths);
+        assert!(config.line_number && config.invert_match);
+        assert_eq!(OutputMode::Count, config.output);
+        assert!(!config.ignore_case);
+        assert_eq!(SearchMode::Regex, config.mode);
+
+        // everything after -- is positional, even if it looks like a flag
+        let config = parse(&["minigrep", "--", "-v", "poem.txt"], &[]).unwrap();
+        assert_eq!(vec!["-v"], config.patterns);
+        assert!(!config.invert_match);
+
+        let config = parse(&["minigrep", "to", "poem.txt", "-", "src"], &[]).unwrap();
+        assert_eq!(vec!["poem.txt", "-", "src"], config.file_paths);
+        assert!(!config.hidden && !config.no_ignore);
+
+        let config = parse(&["minigrep", "--hidden", "--no-ignore", "to", "."], &[]).unwrap();
+        assert!(config.hidden && config.no_ignore);
+    }
+
+    #[test]
+    fn config_flags_win_over_env() {
+        let env = [("IGNORE_CASE", "1"), ("INCLUDE_GLOBS", "*.txt,*.md")];
+        let config = parse(&["minigrep", "to", "poem.txt"], &env).unwrap();
+        assert!(config.ignore_case);
+        assert_eq!(vec!["*.txt", "*.md"], config.include);
+
+        let config = parse(&["minigrep", "--no-ignore-case", "--include=*.rs", "to", "."], &env).unwrap();
+        assert!(!config.ignore_case);
+        assert_eq!(vec!["*.rs"], config.include);
+
+        let config = parse(&["minigrep", "-i", "to", "poem.txt"], &[]).unwrap();
+        assert!(config.ignore_case);
+    }
+
+    #[test]
+    fn config_precedence() {
+        let parse = |args: &[&str], rc: &RcFile, env: &[(&str, &str)]| {
+            let args = args.iter().map(|a| a.to_string());
+            Config::from_sources(args, rc, |key| env.iter().find(|(k, _)| *k == key).map(|(_, v)| v.to_string()))
+        };
+        let rc = RcFile::parse(
+            ".minigreprc",
+            "ignore-case = true\ninclude = *.rs\ncontext = 2\ncolor = always\ncolors = \"ln=33\"\n",
+        )
+        .unwrap();
+        let env = [("INCLUDE_GLOBS", "*.md"), ("MINIGREP_COLORS", "ln=35")];
+        let args = ["minigrep", "to", "."];
+
+        // built-in defaults
+        let config = parse(&args, &RcFile::default(), &[]).unwrap();
+        assert!(!config.ignore_case);
+        assert!(config.include.is_empty());
+        assert_eq!((0, 0), (config.before_context, config.after_context));
+        assert_eq!((ColorChoice::Auto, ""), (config.color, config.colors.as_str()));
+
+        // the file overrides the defaults
+        let config = parse(&args, &rc, &[]).unwrap();
+        assert!(config.ignore_case);
+        assert_eq!(vec!["*.rs"], config.include);
+        assert_eq!((2, 2), (config.before_context, config.after_context));
+        assert_eq!((ColorChoice::Always, "ln=33"), (config.color, config.colors.as_str()));
+
+        // the environment overrides the file, where it says anything
+        let config = parse(&args, &rc, &env).unwrap();
+        assert!(config.ignore_case);
+        assert_eq!(vec!["*.md"], config.include);
+        assert_eq!("ln=35", config.colors);
+
+        // and the command line overrides them all
+        let cli = ["minigrep", "--no-ignore-case", "--include=*.txt", "-A", "0", "--color=never", "to", "."];
+        let config = parse(&cli, &rc, &env).unwrap();
+        assert!(!config.ignore_case);
+        assert_eq!(vec!["*.txt"], config.include);
+        assert_eq!((2, 0), (config.before_context, config.after_context));
+        assert_eq!(ColorChoice::Never, config.color);
+
+        // every setting the file can turn on, the command line can turn off again
+        let rc = RcFile::parse(
+            ".minigreprc",
+            "word-regexp = true\nline-regexp = true\nline-number = true\nbyte-offset = true\n\
+             hidden = true\nno-ignore = true\nlossy = true\nmax-count = 3\n",
+        )
+        .unwrap();
+        let settings = |c: &Config| {
+            [c.whole_word, c.whole_line, c.line_number, c.byte_offset, c.hidden, c.no_ignore, c.lossy, c.max_count == Some(3)]
+        };
+        assert_eq!([true; 8], settings(&parse(&args, &rc, &[]).unwrap()));
+        let flags = [
+            "--no-word-regexp",
+            "--no-line-regexp",
+            "--no-line-number",
+            "--no-byte-offset",
+            "--no-hidden",
+            "--ignore",
+            "--no-lossy",
+            "--no-max-count",
+        ];
+        for (i, flag) in flags.into_iter().enumerate() {
+            let mut expected = [true; 8];
+            expected[i] = false;
+            assert_eq!(expected, settings(&parse(&["minigrep", flag, "to", "."], &rc, &[]).unwrap()), "{flag}");
+        }
+        // the last one on the command line wins
+        let config = parse(&["minigrep", "-m", "1", "--no-max-count", "-n", "--no-line-number", "-m", "2", "to", "."], &rc, &[]).unwrap();
+        assert_eq!((Some(2), false), (config.max_count, config.line_number));
+
+        let rc = RcFile::parse(".minigreprc", "hidden = true\nfrobnicate = 1\n").unwrap();
+        assert_eq!(
+            Err(ArgError::ConfigFile {
+                path: ".minigreprc".to_string(),
+                reason: "line 2: unknown setting 'frobnicate'".to_string(),
+            }),
+            parse(&args, &rc, &[]).map(|_| ()),
+        );
+    }
+
+    #[test]
+    fn config_errors() {
+        assert_eq!(Err(ArgError::UnknownFlag("-z".to_string())), parse(&["minigrep", "-iz", "to", "poem.txt"], &[]).map(|_| ()));
+        assert_eq!(Err(ArgError::UnknownFlag("--fast".to_string())), parse(&["minigrep", "--fast", "to", "poem.txt"], &[]).map(|_| ()));
+        assert_eq!(Err(ArgError::UnknownFlag("--fast".to_string())), parse(&["minigrep", "--fast=yes", "to", "poem.txt"], &[]).map(|_| ()));
+        assert_eq!(Err(ArgError::MissingValue("--include".to_string())), parse(&["minigrep", "to", "poem.txt", "--include"], &[]).map(|_| ()));
+        assert_eq!(Err(ArgError::UnexpectedValue("--count".to_string())), parse(&["minigrep", "--count=3", "to", "poem.txt"], &[]).map(|_| ()));
+        assert_eq!(Err(ArgError::MissingQuery), parse(&["minigrep"], &[]).map(|_| ()));
+        assert_eq!(Err(ArgError::MissingFilePath), parse(&["minigrep", "to"], &[]).map(|_| ()));
+        assert_eq!(
+            Err(ArgError::Conflicts { flag: "--watch".to_string(), with: "--count".to_string() }),
+            parse(&["minigrep", "--watch", "-c", "to", "poem.txt"], &[]).map(|_| ()),
+        );
+        assert_eq!(
+            Err(ArgError::Conflicts { flag: "--watch".to_string(), with: "-".to_string() }),
+            parse(&["minigrep", "--watch", "to", "-"], &[]).map(|_| ()),
+        );
+        assert!(matches!(parse(&["minigrep", "--watch=0", "to", "poem.txt"], &[]), Err(ArgError::InvalidValue { .. })));
+    }
+
+    #[test]
+    fn match_positions() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three
+Trust me.";
+        let config = parse(&["minigrep", "-i", "rust", "poem.txt"], &[]).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+
+        assert_eq!(
+            vec![
+                Match { line_number: 1, byte_offset: 0, line: "Rust:", span: (0, 4) },
+                Match { line_number: 4, byte_offset: 41, line: "Trust me.", span: (1, 5) },
+            ],
+            search_matches(&matcher, contents),
+        );
+    }
+
+    #[test]
+    fn searcher() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three
+Trust me.";
+        let matcher = Matcher::CaseInsensitive(CaseFolder::new("rust", false));
+
+        let mut searcher = Searcher::new(&matcher, contents);
+        assert_eq!(Some("Rust:"), searcher.next().map(|m| m.line));
+        // picks up where it left off
+        assert_eq!(Some((4, 41)), searcher.next().map(|m| (m.line_number, m.byte_offset)));
+        assert_eq!(None, searcher.next());
+
+        let matcher = Matcher::Literal("e".to_string());
+        let lines: Vec<&str> = Searcher::new(&matcher, contents)
+            .filter(|m| m.line.ends_with('.'))
+            .take(1)
+            .map(|m| m.line)
+            .collect();
+        assert_eq!(vec!["safe, fast, productive."], lines);
+        assert_eq!(3, Searcher::new(&matcher, contents).count());
+    }
+
+    #[test]
+    fn config_color() {
+        assert_eq!(ColorChoice::Auto, parse(&["minigrep", "to", "poem.txt"], &[]).unwrap().color);
+        assert_eq!(ColorChoice::Auto, parse(&["minigrep", "--color", "to", "poem.txt"], &[]).unwrap().color);
+        assert_eq!(ColorChoice::Never, parse(&["minigrep", "--color=never", "to", "poem.txt"], &[]).unwrap().color);
+        assert_eq!(
+            Err(ArgError::InvalidValue { flag: "--color".to_string(), value: "pink".to_string() }),
+            parse(&["minigrep", "--color=pink", "to", "poem.txt"], &[]).map(|_| ()),
+        );
+    }
+
+    #[test]
+    fn config_context() {
+        let config = parse(&["minigrep", "-C2", "-A", "5", "to", "poem.txt"], &[]).unwrap();
+        assert_eq!((2, 5), (config.before_context, config.after_context));
+
+        assert_eq!(
+            Err(ArgError::InvalidValue { flag: "-B".to_string(), value: "x".to_string() }),
+            parse(&["minigrep", "-Bx", "to", "poem.txt"], &[]).map(|_| ()),
+        );
+    }
+
+    #[test]
+    fn config_patterns() {
+        // with -e every positional is a path
+        let config = parse(&["minigrep", "-e", "safe", "--regexp=three", "poem.txt", "notes.txt"], &[]).unwrap();
+        assert_eq!(vec!["safe", "three"], config.patterns);
+        assert_eq!(vec!["poem.txt", "notes.txt"], config.file_paths);
+
+        let path = std::env::temp_dir().join(format!("minigrep-patterns-{}", std::process::id()));
+        std::fs::write(&path, "safe\r\nthree\n").unwrap();
+        let path = path.to_str().unwrap();
+        let config = parse(&["minigrep", "-e", "Rust", "-f", path, "poem.txt"], &[]).unwrap();
+        assert_eq!(vec!["Rust", "safe", "three"], config.patterns);
+        std::fs::write(path, "").unwrap();
+        assert!(parse(&["minigrep", "-f", path, "poem.txt"], &[]).unwrap().patterns.is_empty());
+        std::fs::remove_file(path).unwrap();
+
+        assert!(matches!(
+            parse(&["minigrep", "-f", "no/such/patterns.txt", "poem.txt"], &[]),
+            Err(ArgError::PatternFile { .. }),
+        ));
+        assert_eq!(Err(ArgError::MissingFilePath), parse(&["minigrep", "-e", "to"], &[]).map(|_| ()));
+    }
+
+    #[test]
+    fn multiple_patterns() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+        let lines = |args: &[&str]| matching_lines(args, contents);
+        assert_eq!(vec!["safe, fast, productive.", "Pick three."], lines(&["minigrep", "-e", "fast", "-e", "three", "-"]));
+        assert_eq!(vec!["Rust:", "Trust me."], lines(&["minigrep", "-i", "-e", "RUST", "-e", "ME", "-"]));
+        assert_eq!(vec!["Rust:", "Pick three."], lines(&["minigrep", "-E", "-e", "^R", "-e", "x|thr", "-"]));
+
+        let config = parse(&["minigrep", "-E", "-e", "ok", "-e", "(bad", "-"], &[]).unwrap();
+        match Matcher::new(&config) {
+            Err(e @ MinigrepError::PatternAt { index: 2, source: regex::RegexError::UnclosedGroup(0), .. }) => {
+                assert_eq!("invalid pattern 2 '(bad': unclosed group opened at position 0", e.to_string());
+            }
+            Err(other) => panic!("expected an error in the second pattern, got {other:?}"),
+            Ok(_) => panic!("expected an error in the second pattern"),
+        }
+    }
+
+    #[test]
+    fn replace() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.";
+        let replaced = |args: &[&str]| {
+            let config = parse(args, &[]).unwrap();
+            search_replace(&Matcher::new(&config).unwrap(), &config.replacement().unwrap(), contents)
+        };
+        assert_eq!(vec!["safe, quick, productive."], replaced(&["minigrep", "--replace", "quick", "fast", "-"]));
+        assert_eq!(vec!["fast and safe, productive."], replaced(&["minigrep", "-E", "--replace=${2} and $1", r"(\w+), (\w+)", "-"]));
+        assert_eq!(
+            vec!["Rust<:>", "safe, fast, productive<.>", "Pick three<.>"],
+            replaced(&["minigrep", "-E", "--replace", "<$0>", "[:.]$", "-"]),
+        );
+        // $ is just a $ in fixed string mode
+        assert_eq!(vec!["Pick $1."], replaced(&["minigrep", "--replace", "$1", "three", "-"]));
+
+        assert_eq!(
+            Err(ArgError::Requires { flag: "--write".to_string(), needs: "--replace".to_string() }),
+            parse(&["minigrep", "--write", "fast", "poem.txt"], &[]).map(|_| ()),
+        );
+        assert_eq!(
+            Err(ArgError::Conflicts { flag: "--write".to_string(), with: "--lossy".to_string() }),
+            parse(&["minigrep", "--write", "--lossy", "--replace", "quick", "fast", "poem.txt"], &[]).map(|_| ()),
+        );
+    }
+
+    #[test]
+    fn write_changes_only_what_the_preview_shows() {
+        let path = env::temp_dir().join(format!("minigrep-write-{}.txt", std::process::id()));
+        let path_arg = path.to_str().unwrap();
+        let write = |args: &[&str]| {
+            std::fs::write(&path, "fast\nslow\nfast\n").unwrap();
+            let args: Vec<&str> = ["minigrep", "--write", "--replace", "quick"].iter().chain(args).chain([&path_arg]).copied().collect();
+            run(parse(&args, &[]).unwrap()).unwrap();
+            std::fs::read_to_string(&path).unwrap()
+        };
+        assert_eq!("quick\nslow\nquick\n", write(&["fast"]));
+        assert_eq!("quick\nslow\nfast\n", write(&["-m", "1", "fast"]));
+        // context lines are printed as they are, and stay that way
+        assert_eq!("quick\nslow\nfast\n", write(&["-m", "1", "-A", "2", "fast"]));
+        assert_eq!("fast\nslow\nfast\n", write(&["-v", "fast"]));
+        // -l stops printing after the first hit but the whole file is rewritten
+        assert_eq!("quick\nslow\nquick\n", write(&["-l", "fast"]));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    // Produces the same line over and over without ever holding more than one copy of it
+    struct RepeatReader {
+        line: &'static [u8],
+        remaining: usize,
+        pos: usize,
+    }
+
+    impl io::Read for RepeatReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let mut written = 0;
+            while written < buf.len() && self.remaining > 0 {
+                let n = (self.line.len() - self.pos).min(buf.len() - written);
+                buf[written..written + n].copy_from_slice(&self.line[self.pos..self.pos + n]);
+                written += n;
+                self.pos += n;
+                if self.pos == self.line.len() {
+                    self.pos = 0;
+                    self.remaining -= 1;
+                }
+            }
+            Ok(written)
+        }
+    }
+
+    #[test]
+    fn streaming_search() {
+        let config = parse(&["minigrep", "fast", "-"], &[]).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let reader = BufReader::new(RepeatReader {
+            line: b"safe, fast, productive.\r\nPick three\n",
+            remaining: 200_000,
+            pos: 0,
+        });
+        let mut sink = output::CountPrinter::new(Vec::new(), false);
+        let stats = search_reader(&matcher, false, None, false, "(standard input)", reader, &mut sink).unwrap();
+        assert_eq!("200000\n", String::from_utf8(sink.into_inner()).unwrap());
+        assert_eq!(200_000, stats.matched_lines);
+        assert_eq!(200_000 * 36, stats.bytes_searched);
+    }
+
+    #[test]
+    fn compressed_input() {
+        let config = parse(&["minigrep", "-n", "road", "poem.txt.gz"], &[]).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let options = PrintOptions { line_number: true, ..PrintOptions::default() };
+        let input = Input { path: PathBuf::from("poem.txt.gz"), walked: false };
+        let mut output = Vec::new();
+        let stats = search_input(&config, &matcher, options, &input, &mut output).unwrap();
+        assert_eq!("4:Two roads diverged in a yellow wood,\n24:Two roads diverged in a wood, and I—\n", String::from_utf8(output).unwrap());
+        assert_eq!(2, stats.matched_lines);
+
+        // a cut off copy is skipped when found by walking, an error when named
+        let path = env::temp_dir().join(format!("minigrep-corrupt-{}.gz", std::process::id()));
+        std::fs::write(&path, &include_bytes!("../poem.txt.gz")[..40]).unwrap();
+        let mut input = Input { path: path.clone(), walked: true };
+        let search = |input: &Input| search_input(&config, &matcher, PrintOptions::default(), input, &mut Vec::new());
+        assert_eq!(0, search(&input).unwrap().searches);
+        input.walked = false;
+        assert_eq!(ErrorKind::UnexpectedEof, search(&input).unwrap_err().kind());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn errors_carry_paths() {
+        let config = parse(&["minigrep", "to", "no/such/file.txt"], &[]).unwrap();
+        match run(config) {
+            Err(MinigrepError::Incomplete { failed: 1, inputs: 1, worst }) => match *worst {
+                MinigrepError::Io { path, source } => {
+                    assert_eq!(Path::new("no/such/file.txt"), path);
+                    assert_eq!(ErrorKind::NotFound, source.kind());
+                }
+                other => panic!("expected an Io error, got {other:?}"),
+            },
+            other => panic!("expected an Io error, got {other:?}"),
+        }
+
+        let config = parse(&["minigrep", "-E", "(to", "poem.txt"], &[]).unwrap();
+        assert!(matches!(run(config), Err(MinigrepError::Pattern(regex::RegexError::UnclosedGroup(0)))));
+        let config = parse(&["minigrep", "--include", "*.{rs", "to", "src"], &[]).unwrap();
+        assert!(matches!(run(config), Err(MinigrepError::Glob(_))));
+    }
+
+    #[test]
+    fn keeps_going_after_errors() {
+        let config = parse(&["minigrep", "zzz", "poem.txt", "nope.txt", "poem.txt", "no/such/dir/x"], &[]).unwrap();
+        match run(config) {
+            Err(e @ MinigrepError::Incomplete { failed: 2, inputs: 4, .. }) => {
+                assert_eq!("2 of 4 inputs couldn't be searched", e.to_string());
+                assert_eq!(4, e.exit_code());
+            }
+            other => panic!("expected the run to finish, got {other:?}"),
+        }
+
+        // the `--` between files only comes before a file that printed something
+        let mut out = FileOutput::new(Vec::new(), Some("--".to_string()));
+        for file in ["one\n", "", "two\n"] {
+            out.next_file();
+            out.write_all(file.as_bytes()).unwrap();
+        }
+        assert_eq!("one\n--\ntwo\n", String::from_utf8(out.out).unwrap());
+    }
+
+    #[test]
+    fn streaming_max_count() {
+        let config = parse(&["minigrep", "-m", "3", "fast", "-"], &[]).unwrap();
+        assert_eq!(Some(3), config.max_count);
+        let matcher = Matcher::new(&config).unwrap();
+        let count = |invert: bool, max_count: Option<usize>| {
+            let reader = BufReader::new(RepeatReader {
+                line: b"safe, fast, productive.\r\nPick three\n",
+                remaining: 200_000,
+                pos: 0,
+            });
+            let mut sink = output::CountPrinter::new(Vec::new(), false);
+            let stats = search_reader(&matcher, invert, max_count, false, "-", reader, &mut sink).unwrap();
+            (stats.matched_lines, stats.bytes_searched)
+        };
+        // it stops reading at the third match, in the middle of the input
+        assert_eq!((3, 36 * 2 + 25), count(false, config.max_count));
+        assert_eq!((3, 36 * 3), count(true, config.max_count));
+        assert_eq!((0, 0), count(false, Some(0)));
+    }
+
+    #[test]
+    fn binary_and_invalid_utf8() {
+        let config = parse(&["minigrep", "fast", "-"], &[]).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let print = |lossy: bool, input: &[u8]| {
+            let mut out = Vec::new();
+            let mut sink = output::LinePrinter::new(&mut out, PrintOptions::default());
+            let stats = search_reader(&matcher, false, None, lossy, "app.log", input, &mut sink).unwrap();
+            drop(sink);
+            (String::from_utf8(out).unwrap(), stats.matched_lines)
+        };
+        // the lines before the invalid UTF-8 are printed, from there on the file is binary
+        assert_eq!(
+            ("fast\napp.log: binary file matches\n".to_string(), 2),
+            print(false, b"fast\n\xff fast\nfast\n"),
+        );
+        assert_eq!(("fast\n\u{fffd} fast\nfast\n".to_string(), 3), print(true, b"fast\n\xff fast\nfast\n"));
+        // a NUL byte makes it binary from the start, lossy or not
+        assert_eq!(("app.log: binary file matches\n".to_string(), 1), print(true, b"\x7fELF\0\nfast\n"));
+        assert_eq!((String::new(), 0), print(false, b"\x7fELF\0\nslow\n"));
+
+        // counting isn't affected
+        let mut sink = output::CountPrinter::new(Vec::new(), false);
+        let stats = search_reader(&matcher, false, None, false, "-", &b"fast\n\xff\xfe\nfast\n"[..], &mut sink).unwrap();
+        assert_eq!(2, stats.matched_lines);
+    }
+
+    #[test]
+    fn byte_order_marks() {
+        let read = |bytes: Vec<u8>| {
+            let (mut reader, utf16) = decoded(Box::new(&bytes[..])).unwrap();
+            let mut text = String::new();
+            reader.read_to_string(&mut text).unwrap();
+            (text, utf16)
+        };
+        let mut le = vec![0xff, 0xfe];
+        le.extend("Rust:\nsafe".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(("Rust:\nsafe".to_string(), true), read(le));
+        let mut be = vec![0xfe, 0xff];
+        be.extend("Trust me.".encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(("Trust me.".to_string(), true), read(be));
+        assert_eq!(("Rust:".to_string(), false), read(b"\xef\xbb\xbfRust:".to_vec()));
+        assert_eq!(("Rust:".to_string(), false), read(b"Rust:".to_vec()));
+
+        let config = parse(&["minigrep", "--lossy", "fast", "-"], &[]).unwrap();
+        assert!(config.lossy);
+    }
+
+    #[test]
+    fn fuzzy_matcher() {
+        let matcher = FuzzyMatcher::new("productive", 2, None).unwrap();
+        assert_eq!(Some(((12, 22), 0)), matcher.find_at("safe, fast, productive.", 0));
+        // one substitution, and one deletion
+        assert_eq!(Some(((12, 22), 1)), matcher.find_at("safe, fast, prodactive.", 0));
+        assert_eq!(Some(((0, 9), 1)), matcher.find_at("productve", 0));
+        assert_eq!(Some(((12, 21), 2)), matcher.find_at("safe, fast, reductive.", 0));
+        assert_eq!(None, matcher.find_at("safe, fast, seductive.", 0));
+        // only what's after start counts
+        assert_eq!(Some(((13, 22), 1)), matcher.find_at("safe, fast, productive.", 13));
+
+        // spans are in bytes of the line, whatever the folding did
+        let matcher = FuzzyMatcher::new("NAIVE", 1, Some(false)).unwrap();
+        assert_eq!(Some(((4, 10), 1)), matcher.find_at("how naïve", 0));
+        let matcher = FuzzyMatcher::new("strasse", 0, Some(false)).unwrap();
+        assert_eq!(Some(((0, 7), 0)), matcher.find_at("Straße", 0));
+
+        let long = "x".repeat(MAX_FUZZY_LEN + 1);
+        assert_eq!(Some(FuzzyError::TooLong { len: 65, folded: 65 }), FuzzyMatcher::new(&long, 1, None).err());
+        assert!(FuzzyMatcher::new(&long[1..], 1, None).is_ok());
+        // folding ß to ss makes the query longer than what was typed
+        let long = "ß".repeat(40);
+        let err = FuzzyMatcher::new(&long, 1, Some(false)).err().unwrap();
+        assert_eq!(FuzzyError::TooLong { len: 40, folded: 80 }, err);
+        assert_eq!("fuzzy queries can be at most 64 characters, this one has 40 (80 with case ignored)", err.to_string());
+    }
+
+    #[test]
+    fn fuzzy_ranked() {
+        let contents = "\
+Rust:
+safe, fast, productive.
+Pick three.
+Trust me.";
+
+        let matcher = FuzzyMatcher::new("rust", 2, None).unwrap();
+        let ranked: Vec<(usize, &str, (usize, usize))> = search_fuzzy(&matcher, contents)
+            .iter()
+            .map(|m| (m.distance, m.hit.line, m.hit.span))
+            .collect();
+        assert_eq!(
+            vec![
+                (0, "Trust me.", (1, 5)),
+                (1, "Rust:", (1, 4)),
+                (2, "safe, fast, productive.", (8, 10)),
+            ],
+            ranked,
+        );
+
+        let config = parse(&["minigrep", "--fuzzy", "-n", "-m", "2", "rust", "-"], &[]).unwrap();
+        assert_eq!(SearchMode::Fuzzy(1), config.mode);
+        let matcher = Matcher::new(&config).unwrap();
+        let mut out = Vec::new();
+        let options = PrintOptions {
+            line_number: true,
+            ..PrintOptions::default()
+        };
+        let mut sink = output::LinePrinter::new(&mut out, options);
+        let stats = search_ranked(&matcher, config.max_count, false, "-", contents.as_bytes(), &mut sink).unwrap();
+        drop(sink);
+        assert_eq!("4:Trust me.\n1:Rust:\n", String::from_utf8(out).unwrap());
+        assert_eq!((2, contents.len() as u64), (stats.matched_lines, stats.bytes_searched));
+
+        // only -m lines are kept, of equally close ones the first in the file
+        let ranked = |max_count: Option<usize>| {
+            let mut out = Vec::new();
+            let mut sink = output::LinePrinter::new(&mut out, PrintOptions { line_number: true, ..PrintOptions::default() });
+            search_ranked(&matcher, max_count, false, "-", "rust\nrest\nrust\nrost\nrust\n".as_bytes(), &mut sink).unwrap();
+            drop(sink);
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!("1:rust\n3:rust\n", ranked(Some(2)));
+        assert_eq!("1:rust\n3:rust\n5:rust\n2:rest\n", ranked(Some(4)));
+        assert_eq!("", ranked(Some(0)));
+
+        assert_eq!(SearchMode::Fuzzy(3), parse(&["minigrep", "--fuzzy=3", "rust", "-"], &[]).unwrap().mode);
+        assert!(matches!(
+            parse(&["minigrep", "--fuzzy=two", "rust", "-"], &[]),
+            Err(ArgError::InvalidValue { .. })
+        ));
+        let config = parse(&["minigrep", "--fuzzy", &"x".repeat(70), "poem.txt"], &[]).unwrap();
+        assert!(matches!(run(config), Err(MinigrepError::Fuzzy(FuzzyError::TooLong { len: 70, .. }))));
+    }
+}
@@ -1,15 +1,23 @@
+use std::io::ErrorKind;
 use std::{env, process};
+use minigrep::error::MinigrepError;
 use minigrep::{Config, run};
 
 fn main() {
     // env::args() returns an iterator
     let config = Config::new(env::args()).unwrap_or_else(|err| {
         eprintln!("Problem parsing arguments: {err}");
-        process::exit(1);
+        process::exit(MinigrepError::Args(err).exit_code());
     });
 
     if let Err(e) = run(config) {
+        // the reader went away, e.g. `minigrep ... | head`, which isn't worth complaining about
+        if let MinigrepError::Output(io) = &e {
+            if io.kind() == ErrorKind::BrokenPipe {
+                process::exit(0);
+            }
+        }
         eprintln!("Application error: {e}");
-        process::exit(1);
+        process::exit(e.exit_code());
     }
-}
\ No newline at end of file
+}
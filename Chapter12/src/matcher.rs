@@ -0,0 +1,204 @@
+use crate::aho_corasick::AhoCorasick;
+use crate::casefold::CaseFolder;
+use crate::error::MinigrepError;
+use crate::regex::{self, Regex, Slots};
+use crate::{Config, FuzzyMatcher, SearchMode};
+
+// The patterns compiled once from Config and then applied to every line
+pub enum Matcher {
+    Literal(String),
+    CaseInsensitive(CaseFolder),
+    Regex(Regex),
+    // any of several literal patterns
+    Multi(AhoCorasick),
+    // --fuzzy, the closest hit of any of the queries
+    Fuzzy(Vec<FuzzyMatcher>),
+    // hits of the inner matcher that are whole words or whole lines
+    Bounded(Box<Matcher>, Boundary),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Boundary {
+    // -w: no word character right before or after the hit
+    Word,
+    // -x: the hit is the entire line
+    Line,
+}
+
+impl Boundary {
+    fn accepts(self, line: &str, start: usize, end: usize) -> bool {
+        match self {
+            Boundary::Word => {
+                !line[..start].chars().next_back().is_some_and(regex::is_word_char)
+                    && !line[end..].chars().next().is_some_and(regex::is_word_char)
+            }
+            Boundary::Line => start == 0 && end == line.len(),
+        }
+    }
+}
+
+impl Matcher {
+    pub fn new(config: &Config) -> Result<Matcher, MinigrepError> {
+        let matcher = Matcher::unbounded(config)?;
+        // -x wins over -w, a whole line is a whole word anyway
+        Ok(if config.whole_line {
+            Matcher::Bounded(Box::new(matcher), Boundary::Line)
+        } else if config.whole_word {
+            Matcher::Bounded(Box::new(matcher), Boundary::Word)
+        } else {
+            matcher
+        })
+    }
+
+    fn unbounded(config: &Config) -> Result<Matcher, MinigrepError> {
+        let fold = config.ignore_case.then_some(config.turkic_case);
+        Ok(match (config.mode, config.patterns.as_slice()) {
+            // an empty pattern file, nothing can match
+            (_, []) => Matcher::Multi(AhoCorasick::new::<&str>(&[], None)),
+            (SearchMode::Regex, [pattern]) => Matcher::Regex(Regex::with_ignore_case(pattern, config.ignore_case)?),
+            // several regexes become one alternation, each pattern in its own group
+            // so a `|` inside one of them can't leak into the others. Each one is
+            // checked on its own first, so an error says which pattern it's in
+            // and where in that pattern, not where in the alternation.
+            (SearchMode::Regex, patterns) => {
+                for (i, pattern) in patterns.iter().enumerate() {
+                    Regex::new(pattern).map_err(|source| MinigrepError::PatternAt {
+                        index: i + 1,
+                        pattern: pattern.clone(),
+                        source,
+                    })?;
+                }
+                let alternation: Vec<String> = patterns.iter().map(|p| format!("(?:{p})")).collect();
+                Matcher::Regex(Regex::with_ignore_case(&alternation.join("|"), config.ignore_case)?)
+            }
+            (SearchMode::Literal, [pattern]) if config.ignore_case => {
+                Matcher::CaseInsensitive(CaseFolder::new(pattern, config.turkic_case))
+            }
+            (SearchMode::Literal, [pattern]) => Matcher::Literal(pattern.clone()),
+            (SearchMode::Literal, patterns) => Matcher::Multi(AhoCorasick::new(patterns, fold)),
+            (SearchMode::Fuzzy(max_distance), patterns) => Matcher::Fuzzy(
+                patterns
+                    .iter()
+                    .map(|p| FuzzyMatcher::new(p, max_distance, fold))
+                    .collect::<Result<_, _>>()?,
+            ),
+        })
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        self.find(line).is_some()
+    }
+
+    // Byte span of the first hit in the line
+    pub fn find(&self, line: &str) -> Option<(usize, usize)> {
+        self.find_at(line, 0)
+    }
+
+    // Byte span of the first hit starting at or after `start`
+    pub fn find_at(&self, line: &str, start: usize) -> Option<(usize, usize)> {
+        match self {
+            Matcher::Literal(query) => line[start..]
+                .find(query.as_str())
+                .map(|i| (start + i, start + i + query.len())),
+            Matcher::CaseInsensitive(folder) => folder.find_at(line, start),
+            Matcher::Regex(re) => re.find_at(line, start),
+            Matcher::Multi(ac) => ac.find_at(line, start),
+            Matcher::Fuzzy(_) => self.closest_at(line, start).map(|(span, _)| span),
+            Matcher::Bounded(inner, boundary) => {
+                inner.find_at_where(line, start, &|s, e| boundary.accepts(line, s, e))
+            }
+        }
+    }
+
+    // How far the line is from matching: the edit distance of the closest hit
+    // for --fuzzy, 0 for any hit of the exact matchers, None without a hit
+    pub fn distance(&self, line: &str) -> Option<usize> {
+        match self {
+            Matcher::Fuzzy(_) => self.closest_at(line, 0).map(|(_, distance)| distance),
+            Matcher::Bounded(inner, _) => self.find(line).and(inner.distance(line)),
+            _ => self.find(line).map(|_| 0),
+        }
+    }
+
+    // The closest fuzzy hit, the leftmost of those equally close
+    fn closest_at(&self, line: &str, start: usize) -> Option<((usize, usize), usize)> {
+        let Matcher::Fuzzy(queries) = self else { return None };
+        queries
+            .iter()
+            .filter_map(|query| query.find_at(line, start))
+            .min_by_key(|&((s, _), distance)| (distance, s))
+    }
+
+    // Spans of the first hit starting at or after `start` and of its regex groups.
+    // Only regexes have groups, for the rest there's just the hit itself.
+    pub fn captures_at(&self, line: &str, start: usize) -> Option<Slots> {
+        match self {
+            // a regex with nothing to check can search without starting over at each position
+            Matcher::Regex(re) => re.captures_at(line, start),
+            _ => self.captures_at_where(line, start, &|_, _| true),
+        }
+    }
+
+    fn captures_at_where(&self, line: &str, start: usize, accept: &dyn Fn(usize, usize) -> bool) -> Option<Slots> {
+        match self {
+            Matcher::Regex(re) => re.captures_at_where(line, start, accept),
+            Matcher::Bounded(inner, boundary) => {
+                inner.captures_at_where(line, start, &|s, e| accept(s, e) && boundary.accepts(line, s, e))
+            }
+            _ => self.find_at_where(line, start, accept).map(|span| vec![Some(span)]),
+        }
+    }
+
+    // Byte span of the first hit starting at or after `start` that `accept` agrees with
+    fn find_at_where(&self, line: &str, start: usize, accept: &dyn Fn(usize, usize) -> bool) -> Option<(usize, usize)> {
+        match self {
+            // these can match several ways at one place, so let them backtrack
+            Matcher::Regex(re) => re.find_at_where(line, start, accept),
+            Matcher::Multi(ac) => ac.find_at_where(line, start, accept),
+            // the rest have a single hit per place, so try the next place instead
+            _ => {
+                let mut pos = start;
+                loop {
+                    let (s, e) = self.find_at(line, pos)?;
+                    if accept(s, e) {
+                        return Some((s, e));
+                    }
+                    pos = s + line[s..].chars().next()?.len_utf8();
+                }
+            }
+        }
+    }
+
+    // Byte spans of all the non-overlapping hits in the line, left to right
+    pub fn find_all(&self, line: &str) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut start = Some(0);
+        while let Some((s, e)) = start.and_then(|start| self.find_at(line, start)) {
+            spans.push((s, e));
+            start = next_start(line, s, e);
+        }
+        spans
+    }
+
+    // Like find_all, with the groups of each hit
+    pub fn captures_all(&self, line: &str) -> Vec<Slots> {
+        let mut all = Vec::new();
+        let mut start = Some(0);
+        while let Some(slots) = start.and_then(|start| self.captures_at(line, start)) {
+            let Some((s, e)) = slots[0] else { break };
+            all.push(slots);
+            start = next_start(line, s, e);
+        }
+        all
+    }
+}
+
+// Where to look for the hit after (s, e). Empty hits are stepped over so
+// the search always makes progress.
+fn next_start(line: &str, s: usize, e: usize) -> Option<usize> {
+    if e > s {
+        Some(e)
+    } else {
+        line[e..].chars().next().map(|c| e + c.len_utf8())
+    }
+}
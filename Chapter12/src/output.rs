@@ -0,0 +1,603 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+
+use crate::color::{self, Colors};
+use crate::json::Json;
+use crate::matcher::Matcher;
+use crate::replace::Replacement;
+
+// What to print for the lines a search selects
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputMode {
+    // the selected lines themselves (the default)
+    Lines,
+    // -c: the number of selected lines per file
+    Count,
+    // -l: the paths of files with at least one selected line
+    FilesWithMatches,
+    // -L: the paths of files without any selected line
+    FilesWithoutMatch,
+    // --json: one JSON object per line for tools to consume
+    Json,
+}
+
+// Totals for one or more searched files
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stats {
+    pub searches: u64,
+    pub searches_with_match: u64,
+    pub matched_lines: u64,
+    pub bytes_searched: u64,
+}
+
+impl Stats {
+    pub fn add(&mut self, other: &Stats) {
+        self.searches += other.searches;
+        self.searches_with_match += other.searches_with_match;
+        self.matched_lines += other.matched_lines;
+        self.bytes_searched += other.bytes_searched;
+    }
+}
+
+// Receives the lines of each searched file. Each output mode is one Sink.
+pub trait Sink {
+    fn begin_file(&mut self, path: &str) -> io::Result<()>;
+
+    // Every line of the file is passed in order, selected or not
+    fn line(&mut self, line_number: usize, byte_offset: usize, line: &str, selected: bool) -> io::Result<()>;
+
+    fn end_file(&mut self) -> io::Result<()>;
+
+    // The rest of the file is binary. Its lines are still passed in, decoded
+    // lossily, and it's up to the sink whether printing them makes sense.
+    fn binary(&mut self) {}
+
+    // Lets the search skip the rest of a file once the sink has seen enough of it
+    fn wants_more(&self) -> bool {
+        true
+    }
+}
+
+pub fn new_sink<'w, W: Write + 'w>(
+    mode: OutputMode,
+    options: PrintOptions,
+    matcher: &'w Matcher,
+    out: W,
+) -> Box<dyn Sink + 'w> {
+    match mode {
+        OutputMode::Lines => Box::new(LinePrinter::new(out, options).highlight(matcher)),
+        OutputMode::Count => Box::new(CountPrinter::new(out, options.with_path)),
+        OutputMode::FilesWithMatches => Box::new(FilesPrinter::new(out, true)),
+        OutputMode::FilesWithoutMatch => Box::new(FilesPrinter::new(out, false)),
+        OutputMode::Json => Box::new(JsonPrinter::new(out, matcher)),
+    }
+}
+
+// Prints selected lines the way grep does, optionally with context around them:
+//   path:12:selected line
+//   path-13-context line
+//   --                      between groups of lines that aren't adjacent
+#[derive(Debug, Clone, Default)]
+pub struct PrintOptions {
+    // prefix lines with the file they come from
+    pub with_path: bool,
+    pub line_number: bool,
+    pub byte_offset: bool,
+    pub before_context: usize,
+    pub after_context: usize,
+    // None when output isn't colored
+    pub colors: Option<Colors>,
+    // --replace: selected lines are printed with their hits replaced
+    pub replacement: Option<Replacement>,
+}
+
+pub struct LinePrinter<'m, W: Write> {
+    out: W,
+    options: PrintOptions,
+    // finds the spans to color or replace, if any
+    matcher: Option<&'m Matcher>,
+    path: Option<String>,
+    // the path even when it isn't printed, for the binary file message
+    file: String,
+    // the file turned out to be binary, and whether that's been reported yet
+    binary: bool,
+    reported_binary: bool,
+    // up to before_context lines seen since the last printed line,
+    // as (line number, byte offset, text)
+    pending: VecDeque<(usize, usize, String)>,
+    // context lines still to print after the last selected line
+    after_left: usize,
+    last_printed: Option<usize>,
+}
+
+impl<'m, W: Write> LinePrinter<'m, W> {
+    pub fn new(out: W, options: PrintOptions) -> LinePrinter<'m, W> {
+        LinePrinter {
+            out,
+            options,
+            matcher: None,
+            path: None,
+            file: String::new(),
+            binary: false,
+            reported_binary: false,
+            pending: VecDeque::new(),
+            after_left: 0,
+            last_printed: None,
+        }
+    }
+
+    // Colors the hits the matcher finds in each printed line, when colors are on.
+    // The replacement in the options needs the matcher too.
+    pub fn highlight(mut self, matcher: &'m Matcher) -> LinePrinter<'m, W> {
+        self.matcher = Some(matcher);
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    // Colors text with the code that pick chooses, if output is colored
+    fn paint(&self, pick: fn(&Colors) -> &String, text: &str) -> String {
+        match &self.options.colors {
+            Some(colors) => color::paint(pick(colors), text),
+            None => text.to_string(),
+        }
+    }
+
+    // The text of the line as printed: replaced if it's selected and there's a
+    // replacement, with the hits or the replacements colored
+    fn paint_hits(&self, line: &str, selected: bool) -> String {
+        let Some(matcher) = self.matcher else {
+            return line.to_string();
+        };
+        let (line, spans) = match &self.options.replacement {
+            Some(replacement) if selected => replacement.replace_all(matcher, line),
+            _ if self.options.colors.is_some() => (line.to_string(), matcher.find_all(line)),
+            _ => return line.to_string(),
+        };
+        if self.options.colors.is_none() {
+            return line;
+        }
+        let pick: fn(&Colors) -> &String = if selected { |c| &c.selected_match } else { |c| &c.context_match };
+        let mut painted = String::new();
+        let mut last = 0;
+        for (start, end) in spans {
+            painted.push_str(&line[last..start]);
+            painted.push_str(&self.paint(pick, &line[start..end]));
+            last = end;
+        }
+        painted.push_str(&line[last..]);
+        painted
+    }
+
+    fn print(&mut self, line_number: usize, byte_offset: usize, line: &str, sep: char) -> io::Result<()> {
+        let has_context = self.options.before_context > 0 || self.options.after_context > 0;
+        // the `--` before the first group of a later file is up to run's FileOutput
+        let gap = self.last_printed.is_some_and(|last| line_number > last + 1);
+        if has_context && gap {
+            writeln!(self.out, "{}", self.paint(|c| &c.separator, "--"))?;
+        }
+        let selected = sep == ':';
+        let sep = self.paint(|c| &c.separator, &sep.to_string());
+        if let Some(path) = &self.path {
+            write!(self.out, "{}{sep}", self.paint(|c| &c.file_name, path))?;
+        }
+        if self.options.line_number {
+            write!(self.out, "{}{sep}", self.paint(|c| &c.line_number, &line_number.to_string()))?;
+        }
+        if self.options.byte_offset {
+            write!(self.out, "{}{sep}", self.paint(|c| &c.byte_offset, &byte_offset.to_string()))?;
+        }
+        writeln!(self.out, "{}", self.paint_hits(line, selected))?;
+        self.last_printed = Some(line_number);
+        Ok(())
+    }
+}
+
+impl<W: Write> Sink for LinePrinter<'_, W> {
+    fn begin_file(&mut self, path: &str) -> io::Result<()> {
+        self.path = Some(path.to_string()).filter(|_| self.options.with_path);
+        self.file = path.to_string();
+        self.binary = false;
+        self.reported_binary = false;
+        self.pending.clear();
+        self.after_left = 0;
+        self.last_printed = None;
+        Ok(())
+    }
+
+    // Unselected lines are needed to pick out the context lines
+    fn line(&mut self, line_number: usize, byte_offset: usize, line: &str, selected: bool) -> io::Result<()> {
+        // binary lines would garble the terminal, so just say there's a match, like grep
+        if self.binary {
+            if selected {
+                writeln!(self.out, "{}: binary file matches", self.paint(|c| &c.file_name, &self.file))?;
+                self.reported_binary = true;
+            }
+            return Ok(());
+        }
+        if selected {
+            while let Some((number, offset, text)) = self.pending.pop_front() {
+                self.print(number, offset, &text, '-')?;
+            }
+            self.print(line_number, byte_offset, line, ':')?;
+            self.after_left = self.options.after_context;
+        } else if self.after_left > 0 {
+            self.after_left -= 1;
+            self.print(line_number, byte_offset, line, '-')?;
+        } else if self.options.before_context > 0 {
+            if self.pending.len() == self.options.before_context {
+                self.pending.pop_front();
+            }
+            self.pending.push_back((line_number, byte_offset, line.to_string()));
+        }
+        Ok(())
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn binary(&mut self) {
+        self.binary = true;
+    }
+
+    // nothing more to say about a binary file once it matched
+    fn wants_more(&self) -> bool {
+        !self.reported_binary
+    }
+}
+
+// -c: prints how many lines were selected in each file
+pub struct CountPrinter<W: Write> {
+    out: W,
+    with_path: bool,
+    path: String,
+    count: usize,
+}
+
+impl<W: Write> CountPrinter<W> {
+    pub fn new(out: W, with_path: bool) -> CountPrinter<W> {
+        CountPrinter {
+            out,
+            with_path,
+            path: String::new(),
+            count: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Sink for CountPrinter<W> {
+    fn begin_file(&mut self, path: &str) -> io::Result<()> {
+        self.path = path.to_string();
+        self.count = 0;
+        Ok(())
+    }
+
+    fn line(&mut self, _: usize, _: usize, _: &str, selected: bool) -> io::Result<()> {
+        if selected {
+            self.count += 1;
+        }
+        Ok(())
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        if self.with_path {
+            writeln!(self.out, "{}:{}", self.path, self.count)
+        } else {
+            writeln!(self.out, "{}", self.count)
+        }
+    }
+}
+
+// -l and -L: prints the path of each file that has (or doesn't have) a selected line
+pub struct FilesPrinter<W: Write> {
+    out: W,
+    // true for -l, false for -L
+    with_match: bool,
+    path: String,
+    found: bool,
+}
+
+impl<W: Write> FilesPrinter<W> {
+    pub fn new(out: W, with_match: bool) -> FilesPrinter<W> {
+        FilesPrinter {
+            out,
+            with_match,
+            path: String::new(),
+            found: false,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Sink for FilesPrinter<W> {
+    fn begin_file(&mut self, path: &str) -> io::Result<()> {
+        self.path = path.to_string();
+        self.found = false;
+        Ok(())
+    }
+
+    fn line(&mut self, _: usize, _: usize, _: &str, selected: bool) -> io::Result<()> {
+        self.found |= selected;
+        Ok(())
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        if self.found == self.with_match {
+            writeln!(self.out, "{}", self.path)?;
+        }
+        Ok(())
+    }
+
+    // one selected line settles the question for the whole file
+    fn wants_more(&self) -> bool {
+        !self.found
+    }
+}
+
+// --json: JSON Lines output, one record per line
+//   {"type":"begin","data":{"path":{"text":"poem.txt"}}}
+//   {"type":"match","data":{"path":...,"lines":{"text":"..."},"line_number":4,"absolute_offset":49,
+//                           "submatches":[{"match":{"text":"Two"},"start":0,"end":3}]}}
+//   {"type":"end","data":{"path":...,"stats":{"matched_lines":2,"matches":2}}}
+// and a single summary record for the whole run, see write_json_summary
+pub struct JsonPrinter<'m, W: Write> {
+    out: W,
+    // used to find the submatches of each selected line
+    matcher: &'m Matcher,
+    path: String,
+    matched_lines: u64,
+    matches: u64,
+}
+
+impl<'m, W: Write> JsonPrinter<'m, W> {
+    pub fn new(out: W, matcher: &'m Matcher) -> JsonPrinter<'m, W> {
+        JsonPrinter {
+            out,
+            matcher,
+            path: String::new(),
+            matched_lines: 0,
+            matches: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    fn path(&self) -> Json {
+        Json::object([("text", Json::from(self.path.as_str()))])
+    }
+}
+
+fn write_record<W: Write>(out: &mut W, kind: &str, data: Json) -> io::Result<()> {
+    writeln!(out, "{}", Json::object([("type", Json::from(kind)), ("data", data)]))
+}
+
+impl<W: Write> Sink for JsonPrinter<'_, W> {
+    fn begin_file(&mut self, path: &str) -> io::Result<()> {
+        self.path = path.to_string();
+        self.matched_lines = 0;
+        self.matches = 0;
+        let data = Json::object([("path", self.path())]);
+        write_record(&mut self.out, "begin", data)
+    }
+
+    fn line(&mut self, line_number: usize, byte_offset: usize, line: &str, selected: bool) -> io::Result<()> {
+        if !selected {
+            return Ok(());
+        }
+        // lines selected by -v don't contain any hits, so they have no submatches
+        let submatches: Vec<Json> = self
+            .matcher
+            .find_all(line)
+            .into_iter()
+            .map(|(start, end)| {
+                Json::object([
+                    ("match", Json::object([("text", Json::from(&line[start..end]))])),
+                    ("start", Json::from(start)),
+                    ("end", Json::from(end)),
+                ])
+            })
+            .collect();
+        self.matched_lines += 1;
+        self.matches += submatches.len() as u64;
+        let data = Json::object([
+            ("path", self.path()),
+            ("lines", Json::object([("text", Json::from(line))])),
+            ("line_number", Json::from(line_number)),
+            ("absolute_offset", Json::from(byte_offset)),
+            ("submatches", Json::Array(submatches)),
+        ]);
+        write_record(&mut self.out, "match", data)
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        let stats = Json::object([
+            ("matched_lines", Json::from(self.matched_lines)),
+            ("matches", Json::from(self.matches)),
+        ]);
+        let data = Json::object([("path", self.path()), ("stats", stats)]);
+        write_record(&mut self.out, "end", data)
+    }
+}
+
+// The record that closes --json output, with the totals for every file searched
+pub fn write_json_summary<W: Write>(out: &mut W, stats: &Stats) -> io::Result<()> {
+    let stats = Json::object([
+        ("searches", Json::from(stats.searches)),
+        ("searches_with_match", Json::from(stats.searches_with_match)),
+        ("matched_lines", Json::from(stats.matched_lines)),
+        ("bytes_searched", Json::from(stats.bytes_searched)),
+    ]);
+    write_record(out, "summary", Json::object([("stats", stats)]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs every line of contents through a sink, selecting those containing query
+    fn feed(sink: &mut dyn Sink, path: &str, query: &str, contents: &str) {
+        sink.begin_file(path).unwrap();
+        let mut offset = 0;
+        for (index, line) in contents.lines().enumerate() {
+            if !sink.wants_more() {
+                break;
+            }
+            sink.line(index + 1, offset, line, line.contains(query)).unwrap();
+            offset += line.len() + 1;
+        }
+        sink.end_file().unwrap();
+    }
+
+    fn print(options: PrintOptions, query: &str, contents: &str) -> String {
+        let mut printer = LinePrinter::new(Vec::new(), options);
+        feed(&mut printer, "poem.txt", query, contents);
+        String::from_utf8(printer.into_inner()).unwrap()
+    }
+
+    const CONTENTS: &str = "\
+one
+two match
+three
+four
+five
+six match
+seven match
+eight
+nine";
+
+    #[test]
+    fn numbers_and_offsets() {
+        let options = PrintOptions {
+            line_number: true,
+            byte_offset: true,
+            ..Default::default()
+        };
+        assert_eq!("2:4:two match\n6:30:six match\n7:40:seven match\n", print(options, "match", CONTENTS));
+    }
+
+    #[test]
+    fn context_with_separators() {
+        let options = PrintOptions {
+            line_number: true,
+            before_context: 1,
+            after_context: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            "1-one\n2:two match\n3-three\n--\n5-five\n6:six match\n7:seven match\n8-eight\n",
+            print(options, "match", CONTENTS),
+        );
+    }
+
+    #[test]
+    fn overlapping_context_is_merged() {
+        let options = PrintOptions {
+            after_context: 3,
+            ..Default::default()
+        };
+        // the after context of line 2 reaches line 5, right before the next match, so there's no separator
+        assert_eq!(
+            "two match\nthree\nfour\nfive\nsix match\nseven match\neight\nnine\n",
+            print(options, "match", CONTENTS),
+        );
+    }
+
+    #[test]
+    fn no_separator_before_a_file() {
+        let options = PrintOptions {
+            with_path: true,
+            after_context: 1,
+            ..Default::default()
+        };
+        let mut printer = LinePrinter::new(Vec::new(), options);
+        feed(&mut printer, "a.txt", "match", "match");
+        feed(&mut printer, "b.txt", "match", "match\nafter");
+        assert_eq!("a.txt:match\nb.txt:match\nb.txt-after\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn count_printer() {
+        let mut printer = CountPrinter::new(Vec::new(), false);
+        feed(&mut printer, "poem.txt", "match", CONTENTS);
+        assert_eq!("3\n", String::from_utf8(printer.into_inner()).unwrap());
+
+        let mut printer = CountPrinter::new(Vec::new(), true);
+        feed(&mut printer, "a.txt", "match", CONTENTS);
+        feed(&mut printer, "b.txt", "nothing", CONTENTS);
+        assert_eq!("a.txt:3\nb.txt:0\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn files_with_matches() {
+        let mut printer = FilesPrinter::new(Vec::new(), true);
+        feed(&mut printer, "a.txt", "match", CONTENTS);
+        // stops reading a.txt after its first match
+        assert!(!printer.wants_more());
+        feed(&mut printer, "b.txt", "nothing", CONTENTS);
+        feed(&mut printer, "c.txt", "nine", CONTENTS);
+        assert_eq!("a.txt\nc.txt\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn files_without_match() {
+        let mut printer = FilesPrinter::new(Vec::new(), false);
+        feed(&mut printer, "a.txt", "match", CONTENTS);
+        feed(&mut printer, "b.txt", "nothing", CONTENTS);
+        assert_eq!("b.txt\n", String::from_utf8(printer.into_inner()).unwrap());
+    }
+
+    #[test]
+    fn json_records() {
+        let matcher = Matcher::Literal("o".to_string());
+        let mut printer = JsonPrinter::new(Vec::new(), &matcher);
+        feed(&mut printer, "a.txt", "two", CONTENTS);
+        write_json_summary(&mut printer.out, &Stats {
+            searches: 1,
+            searches_with_match: 1,
+            matched_lines: 1,
+            bytes_searched: 57,
+        })
+        .unwrap();
+
+        let expected = [
+            r#"{"type":"begin","data":{"path":{"text":"a.txt"}}}"#,
+            r#"{"type":"match","data":{"path":{"text":"a.txt"},"lines":{"text":"two match"},"line_number":2,"absolute_offset":4,"submatches":[{"match":{"text":"o"},"start":2,"end":3}]}}"#,
+            r#"{"type":"end","data":{"path":{"text":"a.txt"},"stats":{"matched_lines":1,"matches":1}}}"#,
+            r#"{"type":"summary","data":{"stats":{"searches":1,"searches_with_match":1,"matched_lines":1,"bytes_searched":57}}}"#,
+        ];
+        let output = String::from_utf8(printer.into_inner()).unwrap();
+        assert_eq!(expected.to_vec(), output.lines().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn colored_lines() {
+        let options = PrintOptions {
+            with_path: true,
+            line_number: true,
+            colors: Some(Colors::parse("fn=:ln=")),
+            ..Default::default()
+        };
+        let matcher = Matcher::Literal("e".to_string());
+        let mut printer = LinePrinter::new(Vec::new(), options).highlight(&matcher);
+        feed(&mut printer, "a.txt", "three", CONTENTS);
+
+        let sep = color::paint("36", ":");
+        let hit = color::paint("01;31", "e");
+        assert_eq!(
+            format!("a.txt{sep}3{sep}thr{hit}{hit}\n"),
+            String::from_utf8(printer.into_inner()).unwrap(),
+        );
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::io::{self, ErrorKind, Write};
+use std::mem;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+
+// Output is passed on in chunks of about this size
+const CHUNK_SIZE: usize = 64 * 1024;
+// how many chunks a job that isn't first in line may get ahead by before it
+// has to wait, so at most 1 MiB per worker is held in memory
+const CHUNKS_AHEAD: usize = 16;
+
+// One worker per CPU
+pub fn worker_count() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+// What a job hands back: the output it writes as it goes, then its result
+pub enum Piece<T> {
+    Output(Vec<u8>),
+    Done(T),
+}
+
+// Runs `work` on every job using a pool of worker threads and hands the results
+// to `done` in the same order as the jobs, whatever order they finish in.
+// Returning false from `done` stops the pool; jobs that haven't started are skipped.
+pub fn for_each_ordered<J, T>(
+    jobs: &[J],
+    workers: usize,
+    work: impl Fn(&J) -> T + Sync,
+    mut done: impl FnMut(T) -> bool,
+) where
+    J: Sync,
+    T: Send,
+{
+    for_each_streamed(jobs, workers, |job, _| work(job), |piece| match piece {
+        Piece::Output(_) => true,
+        Piece::Done(result) => done(result),
+    });
+}
+
+// Like for_each_ordered, but every job also gets a writer for its output, and
+// `each` gets the output and then the result of one job after the other.
+// The output of the first unfinished job is passed on a line at a time as
+// it's written, the jobs behind it pass theirs on in chunks and can only get a
+// few chunks ahead before they have to wait their turn, so memory doesn't grow
+// with the size of the output.
+pub fn for_each_streamed<J, T>(
+    jobs: &[J],
+    workers: usize,
+    work: impl Fn(&J, &mut dyn Write) -> T + Sync,
+    mut each: impl FnMut(Piece<T>) -> bool,
+) where
+    J: Sync,
+    T: Send,
+{
+    let next_job = AtomicUsize::new(0);
+    // the job whose output is being passed on
+    let front = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (work, next_job, front, stop) = (&work, &next_job, &front, &stop);
+
+    thread::scope(|scope| {
+        // every job gets a channel of its own, its receiver is sent here
+        let (started_tx, started) = mpsc::channel();
+        for _ in 0..workers.clamp(1, jobs.len().max(1)) {
+            let started_tx = started_tx.clone();
+            scope.spawn(move || loop {
+                let index = next_job.fetch_add(1, Ordering::SeqCst);
+                if index >= jobs.len() || stop.load(Ordering::SeqCst) {
+                    break;
+                }
+                let (tx, rx) = mpsc::sync_channel(CHUNKS_AHEAD);
+                // the receivers only go away once we've been told to stop
+                if started_tx.send((index, rx)).is_err() {
+                    break;
+                }
+                let mut out = ChunkWriter {
+                    tx: &tx,
+                    buf: Vec::new(),
+                    index,
+                    front,
+                };
+                let result = work(&jobs[index], &mut out);
+                if out.flush().is_err() || tx.send(Piece::Done(result)).is_err() {
+                    break;
+                }
+            });
+        }
+        // the channel closes once every worker has dropped its sender
+        drop(started_tx);
+
+        // jobs that started before the ones ahead of them in job order
+        let mut waiting = BTreeMap::new();
+        for index in 0..jobs.len() {
+            front.store(index, Ordering::SeqCst);
+            let rx = loop {
+                if let Some(rx) = waiting.remove(&index) {
+                    break rx;
+                }
+                match started.recv() {
+                    Ok((i, rx)) => waiting.insert(i, rx),
+                    Err(_) => return,
+                };
+            };
+            for piece in rx {
+                let finished = matches!(piece, Piece::Done(_));
+                if !each(piece) {
+                    stop.store(true, Ordering::SeqCst);
+                    return;
+                }
+                if finished {
+                    break;
+                }
+            }
+        }
+    });
+}
+
+// Collects what a job writes into chunks and sends them on, or every line
+// as soon as it's complete once the job is at the front
+struct ChunkWriter<'t, T> {
+    tx: &'t SyncSender<Piece<T>>,
+    buf: Vec<u8>,
+    index: usize,
+    front: &'t AtomicUsize,
+}
+
+impl<T> Write for ChunkWriter<'_, T> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        let streaming = self.front.load(Ordering::SeqCst) == self.index && data.contains(&b'\n');
+        if streaming || self.buf.len() >= CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let chunk = mem::replace(&mut self.buf, Vec::with_capacity(CHUNK_SIZE));
+        self.tx
+            .send(Piece::Output(chunk))
+            .map_err(|_| io::Error::new(ErrorKind::BrokenPipe, "the search was stopped"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn results_come_back_in_job_order() {
+        // earlier jobs take longer, so they finish last
+        let jobs: Vec<u64> = (0..8).collect();
+        let mut results = Vec::new();
+        for_each_ordered(
+            &jobs,
+            4,
+            |&job| {
+                thread::sleep(Duration::from_millis((8 - job) * 5));
+                job * 10
+            },
+            |result| {
+                results.push(result);
+                true
+            },
+        );
+        assert_eq!(vec![0, 10, 20, 30, 40, 50, 60, 70], results);
+    }
+
+    #[test]
+    fn stops_when_asked() {
+        let jobs: Vec<u32> = (0..100).collect();
+        let mut results = Vec::new();
+        for_each_ordered(&jobs, 2, |&job| job, |result| {
+            results.push(result);
+            result < 3
+        });
+        assert_eq!(vec![0, 1, 2, 3], results);
+    }
+
+    #[test]
+    fn streams_the_first_job_and_holds_back_the_rest() {
+        // job 0 only finishes once job 1 has written far more than it may get
+        // ahead by, which would never happen if job 0's output waited for the end
+        let jobs = [0, 1];
+        let written = AtomicUsize::new(0);
+        let mut output = Vec::new();
+        for_each_streamed(
+            &jobs,
+            2,
+            |&job, out: &mut dyn Write| {
+                if job == 0 {
+                    out.write_all(b"first\n").unwrap();
+                    out.flush().unwrap();
+                    thread::sleep(Duration::from_millis(50));
+                    return written.load(Ordering::SeqCst);
+                }
+                for _ in 0..(CHUNKS_AHEAD + 8) * 4 {
+                    out.write_all(&[b'x'; CHUNK_SIZE / 4]).unwrap();
+                    written.fetch_add(CHUNK_SIZE / 4, Ordering::SeqCst);
+                }
+                0
+            },
+            |piece| {
+                match piece {
+                    Piece::Output(chunk) => output.push(chunk.len()),
+                    Piece::Done(ahead) => assert!(ahead <= (CHUNKS_AHEAD + 1) * CHUNK_SIZE, "{ahead} bytes ahead"),
+                }
+                true
+            },
+        );
+        assert_eq!(6, output[0]);
+        assert_eq!((CHUNKS_AHEAD + 8) * CHUNK_SIZE, output[1..].iter().sum::<usize>());
+    }
+
+    #[test]
+    fn passes_on_each_line_of_the_first_job() {
+        // the job only finishes once its first line has come out, without flushing it
+        let jobs = [0];
+        let seen = AtomicBool::new(false);
+        let mut finished = None;
+        for_each_streamed(
+            &jobs,
+            1,
+            |_, out: &mut dyn Write| {
+                write!(out, "one").unwrap();
+                writeln!(out).unwrap();
+                let started = std::time::Instant::now();
+                while !seen.load(Ordering::SeqCst) && started.elapsed() < Duration::from_secs(2) {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                seen.load(Ordering::SeqCst)
+            },
+            |piece| {
+                match piece {
+                    Piece::Output(chunk) => seen.store(chunk == b"one\n", Ordering::SeqCst),
+                    Piece::Done(result) => finished = Some(result),
+                }
+                true
+            },
+        );
+        assert_eq!(Some(true), finished);
+    }
+}
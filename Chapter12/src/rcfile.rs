@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::Path;
+
+use crate::args::ArgError;
+
+// Defaults for the command line flags, read from a .minigreprc file so a team
+// can commit the settings for a project next to its code:
+//   # lines starting with # are comments
+//   ignore-case = true
+//   include = *.rs, *.toml        a list, ["*.rs", "*.toml"] works too
+//   context = 2
+//   color = always
+//   colors = "mt=01;32:ln=33"     same format as MINIGREP_COLORS
+// Keys are the long flag names, with `_` allowed in place of `-`, and values
+// can be quoted. The file is looked for in the current directory and then in
+// each of its parents. MINIGREP_CONFIG names a file to use instead, and an
+// empty MINIGREP_CONFIG means no file at all. Whatever the file turns on, the
+// command line can turn off again: --no-line-number, --ignore, --no-max-count...
+
+pub const FILE_NAME: &str = ".minigreprc";
+
+#[derive(Debug, Clone, Default)]
+pub struct RcFile {
+    // for error messages
+    path: String,
+    settings: Vec<Setting>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setting {
+    // starts at 1
+    pub line: usize,
+    pub key: String,
+    // as written, quotes and all
+    pub value: String,
+}
+
+impl RcFile {
+    pub fn parse(path: &str, contents: &str) -> Result<RcFile, ArgError> {
+        let mut rc = RcFile {
+            path: path.to_string(),
+            settings: Vec::new(),
+        };
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(rc.error(i + 1, format!("expected key = value, got '{line}'")));
+            };
+            rc.settings.push(Setting {
+                line: i + 1,
+                key: key.trim().replace('_', "-"),
+                value: value.trim().to_string(),
+            });
+        }
+        Ok(rc)
+    }
+
+    pub fn load(path: &Path) -> Result<RcFile, ArgError> {
+        let label = path.display().to_string();
+        let contents = fs::read_to_string(path).map_err(|e| ArgError::ConfigFile {
+            path: label.clone(),
+            reason: e.to_string(),
+        })?;
+        RcFile::parse(&label, &contents)
+    }
+
+    // The file that applies to a search run from dir. Not having one is fine,
+    // it's just like an empty file.
+    pub fn find(dir: &Path, env: impl Fn(&str) -> Option<String>) -> Result<RcFile, ArgError> {
+        if let Some(path) = env("MINIGREP_CONFIG") {
+            if path.is_empty() {
+                return Ok(RcFile::default());
+            }
+            return RcFile::load(Path::new(&path));
+        }
+        match dir.ancestors().map(|dir| dir.join(FILE_NAME)).find(|path| path.is_file()) {
+            Some(path) => RcFile::load(&path),
+            None => Ok(RcFile::default()),
+        }
+    }
+
+    pub fn settings(&self) -> &[Setting] {
+        &self.settings
+    }
+
+    // An error about the setting on the given line
+    pub fn error(&self, line: usize, reason: String) -> ArgError {
+        ArgError::ConfigFile {
+            path: self.path.clone(),
+            reason: format!("line {line}: {reason}"),
+        }
+    }
+}
+
+impl Setting {
+    pub fn text(&self) -> &str {
+        unquote(&self.value)
+    }
+
+    pub fn flag(&self) -> Result<bool, String> {
+        match self.text() {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            other => Err(format!("'{}' needs true or false, got '{other}'", self.key)),
+        }
+    }
+
+    pub fn number(&self) -> Result<usize, String> {
+        self.text()
+            .parse()
+            .map_err(|_| format!("'{}' needs a number, got '{}'", self.key, self.text()))
+    }
+
+    // Comma separated, optionally in brackets
+    pub fn list(&self) -> Vec<String> {
+        let value = self.value.strip_prefix('[').and_then(|v| v.strip_suffix(']')).unwrap_or(&self.value);
+        value
+            .split(',')
+            .map(|item| unquote(item.trim()))
+            .filter(|item| !item.is_empty())
+            .map(String::from)
+            .collect()
+    }
+}
+
+fn unquote(value: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = value.strip_prefix(quote).and_then(|v| v.strip_suffix(quote)) {
+            return inner;
+        }
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_settings() {
+        let rc = RcFile::parse(
+            ".minigreprc",
+            "# team defaults\n\nignore_case = true\ninclude = [\"*.rs\", '*.toml']\nexclude=target/*, *.lock\ncolors = \"mt=01;32\"\n",
+        )
+        .unwrap();
+        let settings = rc.settings();
+        assert_eq!(4, settings.len());
+        assert_eq!((3, "ignore-case"), (settings[0].line, settings[0].key.as_str()));
+        assert_eq!(Ok(true), settings[0].flag());
+        assert_eq!(vec!["*.rs", "*.toml"], settings[1].list());
+        assert_eq!(vec!["target/*", "*.lock"], settings[2].list());
+        assert_eq!("mt=01;32", settings[3].text());
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        let err = RcFile::parse(".minigreprc", "hidden = true\nno-ignore\n").unwrap_err();
+        assert_eq!(
+            "error in config file '.minigreprc': line 2: expected key = value, got 'no-ignore'",
+            err.to_string(),
+        );
+        let rc = RcFile::parse(".minigreprc", "hidden = yes\ncontext = lots\n").unwrap();
+        assert_eq!(Err("'hidden' needs true or false, got 'yes'".to_string()), rc.settings()[0].flag());
+        assert_eq!(Err("'context' needs a number, got 'lots'".to_string()), rc.settings()[1].number());
+    }
+
+    #[test]
+    fn found_in_parent_directories() {
+        let root = std::env::temp_dir().join(format!("minigrep-rc-{}", std::process::id()));
+        let nested = root.join("src/deep");
+        fs::create_dir_all(&nested).unwrap();
+        fs::write(root.join(FILE_NAME), "hidden = true\n").unwrap();
+        let other = root.join("other.rc");
+        fs::write(&other, "no-ignore = true\ncontext = 1\n").unwrap();
+        let other_path = other.display().to_string();
+
+        let rc = RcFile::find(&nested, |_| None).unwrap();
+        assert_eq!("hidden", rc.settings()[0].key);
+        let rc = RcFile::find(&nested, |_| Some(other_path.clone())).unwrap();
+        assert_eq!(2, rc.settings().len());
+        let rc = RcFile::find(&nested, |_| Some(String::new())).unwrap();
+        assert!(rc.settings().is_empty());
+        // a file named explicitly has to exist
+        let missing = root.join("missing.rc").display().to_string();
+        assert!(matches!(RcFile::find(&nested, |_| Some(missing.clone())), Err(ArgError::ConfigFile { .. })));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
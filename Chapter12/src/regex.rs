@@ -0,0 +1,773 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt;
+
+// A small backtracking regular expression engine.
+// Supported syntax:
+//   literals, `.`, character classes `[a-z]` / `[^0-9]`
+//   escapes \d \D \w \W \s \S, word boundaries \b \B
+//   anchors ^ and $, groups ( ) and (?: ), alternation |
+//   repetition * + ? {n} {n,} {n,m} and their lazy forms (*? +? ?? {n,m}?)
+// Counted repetition goes up to MAX_REPEAT.
+// The pattern is parsed into a tree of Nodes, which is compiled to a
+// program of Insts for the matcher to run.
+
+pub const MAX_REPEAT: u32 = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegexError {
+    UnclosedGroup(usize),
+    UnmatchedParen(usize),
+    UnclosedClass(usize),
+    NothingToRepeat(usize),
+    InvalidRepeat(usize),
+    // a counted repetition above MAX_REPEAT
+    RepeatTooBig(usize),
+    // repetitions inside repetitions that add up to too big a program
+    TooLarge,
+    InvalidRange(usize),
+    TrailingBackslash(usize),
+}
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RegexError::UnclosedGroup(pos) => write!(f, "unclosed group opened at position {pos}"),
+            RegexError::UnmatchedParen(pos) => write!(f, "unmatched ')' at position {pos}"),
+            RegexError::UnclosedClass(pos) => write!(f, "unclosed character class opened at position {pos}"),
+            RegexError::NothingToRepeat(pos) => write!(f, "nothing to repeat at position {pos}"),
+            RegexError::InvalidRepeat(pos) => write!(f, "invalid repetition at position {pos}"),
+            RegexError::RepeatTooBig(pos) => {
+                write!(f, "repetition count above {MAX_REPEAT} at position {pos}")
+            }
+            RegexError::TooLarge => write!(f, "pattern is too large once its repetitions are expanded"),
+            RegexError::InvalidRange(pos) => write!(f, "invalid character range at position {pos}"),
+            RegexError::TrailingBackslash(pos) => write!(f, "trailing backslash at position {pos}"),
+        }
+    }
+}
+
+impl Error for RegexError {}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary(bool),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: u32,
+        max: Option<u32>,
+        greedy: bool,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+    prog: Vec<Inst>,
+    splits: usize,
+    groups: usize,
+    ignore_case: bool,
+}
+
+// Capture slots: index 0 is the whole match, 1.. are the numbered groups
+pub type Slots = Vec<Option<(usize, usize)>>;
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        Regex::with_ignore_case(pattern, false)
+    }
+
+    pub fn with_ignore_case(pattern: &str, ignore_case: bool) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.parse_alternation()?;
+        if parser.pos < parser.chars.len() {
+            // the only way to stop early at the top level is a stray ')'
+            return Err(RegexError::UnmatchedParen(parser.pos));
+        }
+        let (prog, splits) = compile(&node)?;
+        Ok(Regex {
+            prog,
+            splits,
+            groups: parser.groups,
+            ignore_case,
+        })
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    // Returns the byte span of the leftmost match
+    pub fn find(&self, text: &str) -> Option<(usize, usize)> {
+        self.find_at(text, 0)
+    }
+
+    // Like find, but only considers matches starting at or after `start`.
+    // Anchors and word boundaries still see the whole text.
+    pub fn find_at(&self, text: &str, start: usize) -> Option<(usize, usize)> {
+        self.captures_at(text, start).and_then(|slots| slots[0])
+    }
+
+    // Like find_at, but a match only counts if `accept` agrees with its span.
+    // Rejecting a span makes the matcher backtrack and try the other ways the
+    // pattern could match, e.g. the shorter `ab` of `abc|ab` when `c` is followed by a letter.
+    pub fn find_at_where(
+        &self,
+        text: &str,
+        start: usize,
+        accept: &dyn Fn(usize, usize) -> bool,
+    ) -> Option<(usize, usize)> {
+        self.captures_at_where(text, start, accept).and_then(|slots| slots[0])
+    }
+
+    // Spans of the whole match and of every group, for the leftmost match
+    // starting at or after `start`. Groups that took no part in the match are None.
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Slots> {
+        self.search(text, start, None)
+    }
+
+    pub fn captures_at_where(
+        &self,
+        text: &str,
+        start: usize,
+        accept: &dyn Fn(usize, usize) -> bool,
+    ) -> Option<Slots> {
+        self.search(text, start, Some(accept))
+    }
+
+    fn search(&self, text: &str, start: usize, accept: Option<&dyn Fn(usize, usize) -> bool>) -> Option<Slots> {
+        let mut machine = Machine {
+            prog: &self.prog,
+            text,
+            ignore_case: self.ignore_case,
+            visited: Visited::new(self.splits * (text.len() + 1)),
+            jobs: Vec::new(),
+        };
+        let mut slots = vec![None; 2 * (self.groups + 1)];
+        let mut pos = start;
+        while pos <= text.len() {
+            // Where a match fails doesn't depend on where it started, unless
+            // accept looks at the start too. Then it has to be forgotten.
+            if accept.is_some() && pos > start {
+                machine.visited.clear();
+            }
+            slots.fill(None);
+            if let Some(end) = machine.run(pos, &mut slots, accept.unwrap_or(&|_, _| true)) {
+                let mut groups: Slots = slots.chunks(2).map(|pair| pair[0].zip(pair[1])).collect();
+                groups[0] = Some((pos, end));
+                return Some(groups);
+            }
+            match text[pos..].chars().next() {
+                Some(c) => pos += c.len_utf8(),
+                None => break,
+            }
+        }
+        None
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.peek() == Some('|') {
+            self.pos += 1;
+            branches.push(self.parse_concat()?);
+        }
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Node::Alternate(branches))
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_repeat(atom)?);
+        }
+        match nodes.len() {
+            0 => Ok(Node::Empty),
+            1 => Ok(nodes.pop().unwrap()),
+            _ => Ok(Node::Concat(nodes)),
+        }
+    }
+
+    fn parse_repeat(&mut self, mut atom: Node) -> Result<Node, RegexError> {
+        loop {
+            let start = self.pos;
+            let (min, max) = match self.peek() {
+                Some('*') => {
+                    self.pos += 1;
+                    (0, None)
+                }
+                Some('+') => {
+                    self.pos += 1;
+                    (1, None)
+                }
+                Some('?') => {
+                    self.pos += 1;
+                    (0, Some(1))
+                }
+                Some('{') => match self.parse_braces()? {
+                    Some(bounds) => bounds,
+                    // not a valid counted repetition, so `{` is a literal
+                    None => return Ok(atom),
+                },
+                _ => return Ok(atom),
+            };
+            if matches!(atom, Node::Start | Node::End | Node::WordBoundary(_) | Node::Empty) {
+                return Err(RegexError::NothingToRepeat(start));
+            }
+            let greedy = if self.peek() == Some('?') {
+                self.pos += 1;
+                false
+            } else {
+                true
+            };
+            atom = Node::Repeat {
+                node: Box::new(atom),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    // Parses {n}, {n,} or {n,m}. Returns None (and consumes nothing) when the
+    // braces don't form a repetition, in which case they are literal characters.
+    fn parse_braces(&mut self) -> Result<Option<(u32, Option<u32>)>, RegexError> {
+        let start = self.pos;
+        let close = match self.chars[start..].iter().position(|&c| c == '}') {
+            Some(offset) => start + offset,
+            None => return Ok(None),
+        };
+        let inner: String = self.chars[start + 1..close].iter().collect();
+        // digits beyond u32 are too big a count, not literal braces
+        let parse = |s: &str| {
+            let s = s.trim();
+            match s.parse::<u32>() {
+                Ok(n) => Some(n),
+                Err(_) if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => Some(u32::MAX),
+                Err(_) => None,
+            }
+        };
+        let bounds = match inner.split_once(',') {
+            None => parse(&inner).map(|n| (n, Some(n))),
+            Some((lo, "")) => parse(lo).map(|n| (n, None)),
+            Some((lo, hi)) => match (parse(lo), parse(hi)) {
+                (Some(lo), Some(hi)) => Some((lo, Some(hi))),
+                _ => None,
+            },
+        };
+        match bounds {
+            Some((lo, Some(hi))) if hi < lo => Err(RegexError::InvalidRepeat(start)),
+            Some((lo, hi)) if lo.max(hi.unwrap_or(0)) > MAX_REPEAT => Err(RegexError::RepeatTooBig(start)),
+            Some(bounds) => {
+                self.pos = close + 1;
+                Ok(Some(bounds))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        let start = self.pos;
+        let c = self.chars[self.pos];
+        self.pos += 1;
+        match c {
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Start),
+            '$' => Ok(Node::End),
+            '*' | '+' | '?' => Err(RegexError::NothingToRepeat(start)),
+            '(' => {
+                let index = if self.chars[self.pos..].starts_with(&['?', ':']) {
+                    self.pos += 2;
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let inner = self.parse_alternation()?;
+                if self.peek() != Some(')') {
+                    return Err(RegexError::UnclosedGroup(start));
+                }
+                self.pos += 1;
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            '[' => self.parse_class(start),
+            '\\' => self.parse_escape(start),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_escape(&mut self, start: usize) -> Result<Node, RegexError> {
+        let c = self.peek().ok_or(RegexError::TrailingBackslash(start))?;
+        self.pos += 1;
+        Ok(match c {
+            'b' => Node::WordBoundary(true),
+            'B' => Node::WordBoundary(false),
+            _ => match class_escape(c) {
+                Some(item) => Node::Class(Class {
+                    items: vec![item],
+                    negated: false,
+                }),
+                None => Node::Char(literal_escape(c)),
+            },
+        })
+    }
+
+    fn parse_class(&mut self, start: usize) -> Result<Node, RegexError> {
+        let mut items = Vec::new();
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+        let mut first = true;
+        loop {
+            let c = self.peek().ok_or(RegexError::UnclosedClass(start))?;
+            self.pos += 1;
+            // a `]` right after `[` or `[^` is a literal
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+            let lo = if c == '\\' {
+                let e = self.peek().ok_or(RegexError::UnclosedClass(start))?;
+                self.pos += 1;
+                if let Some(item) = class_escape(e) {
+                    items.push(item);
+                    continue;
+                }
+                literal_escape(e)
+            } else {
+                c
+            };
+            // a range, unless the `-` is the last character in the class
+            if self.peek() == Some('-') && self.chars.get(self.pos + 1).is_some_and(|&n| n != ']') {
+                let range_pos = self.pos;
+                self.pos += 1;
+                let mut hi = self.chars[self.pos];
+                self.pos += 1;
+                if hi == '\\' {
+                    let e = self.peek().ok_or(RegexError::UnclosedClass(start))?;
+                    self.pos += 1;
+                    hi = literal_escape(e);
+                }
+                if hi < lo {
+                    return Err(RegexError::InvalidRange(range_pos));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(ClassItem::Char(lo));
+            }
+        }
+        Ok(Node::Class(Class { items, negated }))
+    }
+}
+
+fn class_escape(c: char) -> Option<ClassItem> {
+    match c {
+        'd' => Some(ClassItem::Digit(true)),
+        'D' => Some(ClassItem::Digit(false)),
+        'w' => Some(ClassItem::Word(true)),
+        'W' => Some(ClassItem::Word(false)),
+        's' => Some(ClassItem::Space(true)),
+        'S' => Some(ClassItem::Space(false)),
+        _ => None,
+    }
+}
+
+fn literal_escape(c: char) -> char {
+    match c {
+        'n' => '\n',
+        't' => '\t',
+        'r' => '\r',
+        other => other,
+    }
+}
+
+// Word characters in the Unicode sense: letters, digits, `_`, and the combining
+// marks that attach to them, so the virama in स्ते doesn't split a Hindi word in two.
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || is_combining_mark(c)
+}
+
+// The common blocks of combining marks. Without the general category tables in
+// std this is an approximation, but it covers the scripts in use in this book.
+fn is_combining_mark(c: char) -> bool {
+    matches!(c,
+        '\u{300}'..='\u{36f}'
+        | '\u{483}'..='\u{489}'
+        | '\u{591}'..='\u{5bd}'
+        | '\u{610}'..='\u{61a}'
+        | '\u{64b}'..='\u{65f}'
+        // Brahmic scripts, apart from the danda punctuation
+        | '\u{900}'..='\u{963}'
+        | '\u{966}'..='\u{dff}'
+        | '\u{e31}'
+        | '\u{e34}'..='\u{e3a}'
+        | '\u{e47}'..='\u{e4e}'
+        | '\u{1ab0}'..='\u{1aff}'
+        | '\u{1dc0}'..='\u{1dff}'
+        // zero width non-joiner and joiner
+        | '\u{200c}'..='\u{200d}'
+        | '\u{20d0}'..='\u{20ff}'
+        | '\u{fe00}'..='\u{fe0f}'
+        | '\u{fe20}'..='\u{fe2f}'
+    )
+}
+
+impl Class {
+    fn matches(&self, c: char, ignore_case: bool) -> bool {
+        let hit = self.items.iter().any(|item| item.matches(c))
+            || (ignore_case && case_variants(c).any(|v| self.items.iter().any(|item| item.matches(v))));
+        hit != self.negated
+    }
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Char(x) => c == x,
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Digit(want) => c.is_ascii_digit() == want,
+            ClassItem::Word(want) => is_word_char(c) == want,
+            ClassItem::Space(want) => c.is_whitespace() == want,
+        }
+    }
+}
+
+// Single-character lower and upper case forms of c
+fn case_variants(c: char) -> impl Iterator<Item = char> {
+    let mut lower = c.to_lowercase();
+    let mut upper = c.to_uppercase();
+    let lower = if lower.len() == 1 { lower.next() } else { None };
+    let upper = if upper.len() == 1 { upper.next() } else { None };
+    lower.into_iter().chain(upper)
+}
+
+fn chars_eq(a: char, b: char, ignore_case: bool) -> bool {
+    a == b || (ignore_case && case_variants(a).any(|v| v == b))
+}
+
+// The pattern compiled to a program for a backtracking machine, in the
+// style of Pike's and Thompson's: each instruction either checks the text at
+// the current position or says where to go next. Split tries its first
+// target and, if the rest of the match fails from there, its second one.
+// Repetition is a loop of Splits, so matching a long line needs no more
+// stack than a short one, the alternatives left to try are kept on a Vec.
+#[derive(Debug, Clone)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Start,
+    End,
+    WordBoundary(bool),
+    // record the position in a capture slot, 2i and 2i+1 are the start and end of group i
+    Save(usize),
+    Jmp(usize),
+    // id numbers the Splits, for the memory of where matching has already failed
+    Split { first: usize, second: usize, id: usize },
+    Match,
+}
+
+// Repetitions are compiled to a copy of their body per counted iteration, so
+// `(?:x{1000}){1000}` would take a million instructions
+const MAX_PROGRAM: usize = 100_000;
+
+#[derive(Default)]
+struct Compiler {
+    prog: Vec<Inst>,
+    splits: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, inst: Inst) -> Result<usize, RegexError> {
+        if self.prog.len() >= MAX_PROGRAM {
+            return Err(RegexError::TooLarge);
+        }
+        self.prog.push(inst);
+        Ok(self.prog.len() - 1)
+    }
+
+    // A Split whose targets are filled in by patch once they're known
+    fn split(&mut self) -> Result<usize, RegexError> {
+        self.splits += 1;
+        self.emit(Inst::Split { first: 0, second: 0, id: self.splits - 1 })
+    }
+
+    fn patch(&mut self, at: usize, first: usize, second: usize) {
+        match &mut self.prog[at] {
+            Inst::Split { first: f, second: s, .. } => (*f, *s) = (first, second),
+            Inst::Jmp(target) => *target = first,
+            _ => unreachable!("only Split and Jmp are patched"),
+        }
+    }
+
+    fn compile(&mut self, node: &Node) -> Result<(), RegexError> {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.emit(Inst::Char(*c))?;
+            }
+            Node::Any => {
+                self.emit(Inst::Any)?;
+            }
+            Node::Class(class) => {
+                self.emit(Inst::Class(class.clone()))?;
+            }
+            Node::Start => {
+                self.emit(Inst::Start)?;
+            }
+            Node::End => {
+                self.emit(Inst::End)?;
+            }
+            Node::WordBoundary(want) => {
+                self.emit(Inst::WordBoundary(*want))?;
+            }
+            Node::Group(inner, None) => self.compile(inner)?,
+            Node::Group(inner, Some(i)) => {
+                self.emit(Inst::Save(2 * i))?;
+                self.compile(inner)?;
+                self.emit(Inst::Save(2 * i + 1))?;
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node)?;
+                }
+            }
+            // each branch but the last is tried first, then the ones after it
+            Node::Alternate(branches) => {
+                let (last, rest) = branches.split_last().expect("an alternation has branches");
+                let mut jumps = Vec::new();
+                for branch in rest {
+                    let split = self.split()?;
+                    self.compile(branch)?;
+                    jumps.push(self.emit(Inst::Jmp(0))?);
+                    self.patch(split, split + 1, self.prog.len());
+                }
+                self.compile(last)?;
+                for jump in jumps {
+                    self.patch(jump, self.prog.len(), 0);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.compile(node)?;
+                }
+                // greedy repetition tries another iteration first, lazy tries stopping first
+                let order = |body: usize, out: usize| if *greedy { (body, out) } else { (out, body) };
+                match max {
+                    None => {
+                        let split = self.split()?;
+                        self.compile(node)?;
+                        self.emit(Inst::Jmp(split))?;
+                        let (first, second) = order(split + 1, self.prog.len());
+                        self.patch(split, first, second);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.split()?);
+                            self.compile(node)?;
+                        }
+                        for split in splits {
+                            let (first, second) = order(split + 1, self.prog.len());
+                            self.patch(split, first, second);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn compile(node: &Node) -> Result<(Vec<Inst>, usize), RegexError> {
+    let mut compiler = Compiler::default();
+    compiler.compile(node)?;
+    compiler.emit(Inst::Match)?;
+    Ok((compiler.prog, compiler.splits))
+}
+
+// The (Split, position) pairs matching has been through. Getting to one again
+// means getting to the same rest of the match, which has failed or is still
+// being tried, so it's never worth going on. This is what keeps e.g.
+// `(a|a)*b` from taking exponential time and `(a*)*` from looping forever.
+enum Visited {
+    Bits(Vec<u64>),
+    // when a bit for every pair would take too much memory, e.g. on a huge line
+    Set(HashSet<usize>),
+}
+
+const MAX_VISITED_BITS: usize = 1 << 25;
+
+impl Visited {
+    fn new(states: usize) -> Visited {
+        if states <= MAX_VISITED_BITS {
+            Visited::Bits(vec![0; states.div_ceil(64)])
+        } else {
+            Visited::Set(HashSet::new())
+        }
+    }
+
+    // Whether state is new
+    fn insert(&mut self, state: usize) -> bool {
+        match self {
+            Visited::Bits(bits) => {
+                let (word, bit) = (state / 64, 1 << (state % 64));
+                let new = bits[word] & bit == 0;
+                bits[word] |= bit;
+                new
+            }
+            Visited::Set(set) => set.insert(state),
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Visited::Bits(bits) => bits.fill(0),
+            Visited::Set(set) => set.clear(),
+        }
+    }
+}
+
+// What's left to try when the current path fails
+enum Job {
+    // carry on from an instruction at a position
+    Step(usize, usize),
+    // put a capture slot back the way it was before this path changed it
+    Restore(usize, Option<usize>),
+}
+
+struct Machine<'r, 't> {
+    prog: &'r [Inst],
+    text: &'t str,
+    ignore_case: bool,
+    visited: Visited,
+    jobs: Vec<Job>,
+}
+
+impl Machine<'_, '_> {
+    fn char_at(&self, pos: usize) -> Option<char> {
+        self.text[pos..].chars().next()
+    }
+
+    fn char_before(&self, pos: usize) -> Option<char> {
+        self.text[..pos].chars().next_back()
+    }
+
+    // The end of the first match starting at start that accept agrees with, in
+    // the order the Splits prefer. slots holds the groups of that match.
+    fn run(&mut self, start: usize, slots: &mut [Option<usize>], accept: &dyn Fn(usize, usize) -> bool) -> Option<usize> {
+        self.jobs.clear();
+        self.jobs.push(Job::Step(0, start));
+        while let Some(job) = self.jobs.pop() {
+            let (mut pc, mut pos) = match job {
+                Job::Step(pc, pos) => (pc, pos),
+                Job::Restore(slot, old) => {
+                    slots[slot] = old;
+                    continue;
+                }
+            };
+            // follow the path until it fails, then pick up the latest alternative
+            loop {
+                match &self.prog[pc] {
+                    Inst::Char(x) => match self.char_at(pos) {
+                        Some(c) if chars_eq(c, *x, self.ignore_case) => pos += c.len_utf8(),
+                        _ => break,
+                    },
+                    Inst::Any => match self.char_at(pos) {
+                        Some(c) if c != '\n' => pos += c.len_utf8(),
+                        _ => break,
+                    },
+                    Inst::Class(class) => match self.char_at(pos) {
+                        Some(c) if class.matches(c, self.ignore_case) => pos += c.len_utf8(),
+                        _ => break,
+                    },
+                    Inst::Start if pos != 0 => break,
+                    Inst::End if pos != self.text.len() => break,
+                    Inst::Start | Inst::End => {}
+                    Inst::WordBoundary(want) => {
+                        let before = self.char_before(pos).is_some_and(is_word_char);
+                        let after = self.char_at(pos).is_some_and(is_word_char);
+                        if (before != after) != *want {
+                            break;
+                        }
+                    }
+                    Inst::Save(slot) => {
+                        self.jobs.push(Job::Restore(*slot, slots[*slot]));
+                        slots[*slot] = Some(pos);
+                    }
+                    Inst::Jmp(target) => {
+                        pc = *target;
+                        continue;
+                    }
+                    Inst::Split { first, second, id } => {
+                        if !self.visited.insert(id * (self.text.len() + 1) + pos) {
+                            break;
+                        }
+                        self.jobs.push(Job::Step(*second, pos));
+                        pc = *first;
+                        continue;
+                    }
+                    Inst::Match => {
+                        if accept(start, pos) {
+                            return Some(pos);
+                        }
+                        break;
+                    }
+                }
+                pc += 1;
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,351 @@
+use std::collections::HashSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::matcher::Matcher;
+use crate::output::Sink;
+use crate::regex::Slots;
+
+// The text --replace puts in place of every hit.
+// In regex mode it can refer to the groups of the hit:
+//   $0 or ${0}    the whole hit
+//   $1 or ${1}    the first group, and so on; ${1}x keeps the x out of the number
+//   $$            a literal $
+// A group that took no part in the hit is replaced with nothing, and a `$` that
+// isn't followed by any of these is kept as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replacement {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Group(usize),
+}
+
+impl Replacement {
+    // Without captures the whole text is literal, for fixed string searches
+    pub fn new(text: &str, captures: bool) -> Replacement {
+        if !captures {
+            return Replacement { parts: vec![Part::Text(text.to_string())] };
+        }
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+        while let Some(i) = rest.find('$') {
+            literal.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if let Some(after) = rest.strip_prefix('$') {
+                literal.push('$');
+                rest = after;
+                continue;
+            }
+            match group_reference(rest) {
+                Some((group, len)) => {
+                    if !literal.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Group(group));
+                    rest = &rest[len..];
+                }
+                None => literal.push('$'),
+            }
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Replacement { parts }
+    }
+
+    // The replacement for one hit in line
+    fn expand(&self, line: &str, slots: &Slots, out: &mut String) {
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Group(group) => {
+                    if let Some(Some((s, e))) = slots.get(*group) {
+                        out.push_str(&line[*s..*e]);
+                    }
+                }
+            }
+        }
+    }
+
+    // Replaces every hit in line. Also returns where the replacements ended
+    // up in the new line, so they can be colored.
+    pub fn replace_all(&self, matcher: &Matcher, line: &str) -> (String, Vec<(usize, usize)>) {
+        let mut replaced = String::with_capacity(line.len());
+        let mut spans = Vec::new();
+        let mut last = 0;
+        for slots in matcher.captures_all(line) {
+            let Some((s, e)) = slots[0] else { continue };
+            replaced.push_str(&line[last..s]);
+            let start = replaced.len();
+            self.expand(line, &slots, &mut replaced);
+            spans.push((start, replaced.len()));
+            last = e;
+        }
+        replaced.push_str(&line[last..]);
+        (replaced, spans)
+    }
+
+    // Applies the replacement to the lines of contents with the given line
+    // numbers, keeping the rest and all the line endings as they are
+    pub fn replace_lines(&self, matcher: &Matcher, contents: &str, selected: &HashSet<usize>) -> String {
+        let mut replaced = String::with_capacity(contents.len());
+        for (i, line) in contents.split_inclusive('\n').enumerate() {
+            let text = line.trim_end_matches('\n').trim_end_matches('\r');
+            if selected.contains(&(i + 1)) {
+                replaced.push_str(&self.replace_all(matcher, text).0);
+            } else {
+                replaced.push_str(text);
+            }
+            replaced.push_str(&line[text.len()..]);
+        }
+        replaced
+    }
+}
+
+// `1`, `12` or `{12}` at the start of s: the group number and how many bytes it takes up
+fn group_reference(s: &str) -> Option<(usize, usize)> {
+    if let Some(braced) = s.strip_prefix('{') {
+        let end = braced.find('}')?;
+        let group = braced[..end].parse().ok()?;
+        return Some((group, end + 2));
+    }
+    let digits = s.bytes().take_while(u8::is_ascii_digit).count();
+    let group = s[..digits].parse().ok()?;
+    Some((group, digits))
+}
+
+// Passes every line on to the sink that prints the preview and notes the
+// numbers of the selected ones, so --write changes exactly the lines the
+// preview showed: -m, -v and the context lines are all taken into account.
+// Without record it only passes the lines on.
+pub struct Selection<'s> {
+    sink: &'s mut dyn Sink,
+    record: bool,
+    pub lines: HashSet<usize>,
+}
+
+impl<'s> Selection<'s> {
+    pub fn new(sink: &'s mut dyn Sink, record: bool) -> Selection<'s> {
+        Selection { sink, record, lines: HashSet::new() }
+    }
+}
+
+impl Sink for Selection<'_> {
+    fn begin_file(&mut self, path: &str) -> io::Result<()> {
+        self.sink.begin_file(path)
+    }
+
+    // a sink like the -l one stops wanting lines after the first hit, but the
+    // rest of the file still has to be rewritten
+    fn line(&mut self, line_number: usize, byte_offset: usize, line: &str, selected: bool) -> io::Result<()> {
+        if selected && self.record {
+            self.lines.insert(line_number);
+        }
+        if self.sink.wants_more() {
+            self.sink.line(line_number, byte_offset, line, selected)?;
+        }
+        Ok(())
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        self.sink.end_file()
+    }
+
+    fn binary(&mut self) {
+        self.sink.binary()
+    }
+
+    fn wants_more(&self) -> bool {
+        self.record || self.sink.wants_more()
+    }
+}
+
+// --write: applies the replacement to the selected lines of the file in place.
+// Returns whether anything changed. Binary files are an InvalidInput error,
+// the replacement could break whatever format they're in. A file that isn't
+// valid UTF-8 is an InvalidData error. The caller skips both with a warning.
+pub fn rewrite_file(path: &Path, matcher: &Matcher, replacement: &Replacement, selected: &HashSet<usize>) -> io::Result<bool> {
+    if selected.is_empty() {
+        return Ok(false);
+    }
+    let contents = fs::read(path)?;
+    if crate::is_binary(&contents) {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "binary file"));
+    }
+    let contents = String::from_utf8(contents).map_err(|e| io::Error::new(ErrorKind::InvalidData, e))?;
+    // the search didn't see the BOM, so neither may ^ or -x here, but it stays in the file
+    let (bom, text) = match contents.strip_prefix('\u{feff}') {
+        Some(text) => ("\u{feff}", text),
+        None => ("", contents.as_str()),
+    };
+    let replaced = replacement.replace_lines(matcher, text, selected);
+    if replaced == text {
+        return Ok(false);
+    }
+    write_atomically(path, format!("{bom}{replaced}").as_bytes())?;
+    Ok(true)
+}
+
+// Writes the new contents to a temporary file next to path and renames it over
+// path, so anything reading the file sees either all of the old contents or all
+// of the new, never a half written file. A symlink is followed, the file it
+// points to is replaced and the link stays.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let path = &fs::canonicalize(path)?;
+    // keep the mode of the original, e.g. an executable script stays executable
+    let permissions = fs::metadata(path)?.permissions();
+    let (temp, mut file) = create_temp(path)?;
+    let result = (|| {
+        file.set_permissions(permissions)?;
+        file.write_all(contents)?;
+        // make sure the data is on disk before the rename makes it visible
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+// A new temporary file next to path. create_new fails instead of following
+// a file or symlink someone put there, so on a clash the next name is tried.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, fs::File)> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let temp = temp_path(path, NEXT.fetch_add(1, Ordering::Relaxed));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// The rename is only atomic within a file system, so the temporary file goes in the same directory
+fn temp_path(path: &Path, n: usize) -> PathBuf {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    path.with_file_name(format!(".{name}.minigrep-{}-{n}.tmp", process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::Regex;
+
+    #[test]
+    fn parses_references() {
+        let replacement = Replacement::new("$1-${2}x $$ $a $", true);
+        assert_eq!(
+            vec![
+                Part::Group(1),
+                Part::Text("-".to_string()),
+                Part::Group(2),
+                Part::Text("x $ $a $".to_string()),
+            ],
+            replacement.parts,
+        );
+        assert_eq!(vec![Part::Text("$1".to_string())], Replacement::new("$1", false).parts);
+    }
+
+    #[test]
+    fn replaces_every_hit() {
+        let matcher = Matcher::Regex(Regex::new(r"(\w+)@(\w+)").unwrap());
+        let replacement = Replacement::new("$2 at $1 ($0)$3", true);
+        assert_eq!(
+            ("b at a (a@b), d at c (c@d)".to_string(), vec![(0, 12), (14, 26)]),
+            replacement.replace_all(&matcher, "a@b, c@d"),
+        );
+
+        let matcher = Matcher::Literal("fast".to_string());
+        assert_eq!(
+            "safe, quick, productive.\r\nquick\n",
+            Replacement::new("quick", false).replace_lines(&matcher, "safe, fast, productive.\r\nfast\n", &HashSet::from([1, 2])),
+        );
+        // lines that weren't selected keep their hits
+        assert_eq!(
+            "fast\nquick\nfast",
+            Replacement::new("quick", false).replace_lines(&matcher, "fast\nfast\nfast", &HashSet::from([2])),
+        );
+    }
+
+    #[test]
+    fn rewrites_in_place() {
+        let dir = std::env::temp_dir().join(format!("minigrep-replace-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("poem.txt");
+        fs::write(&path, "Rust:\nsafe, fast, productive.\nfast\n").unwrap();
+        let matcher = Matcher::Literal("fast".to_string());
+        let quick = Replacement::new("quick", false);
+
+        // only the selected line changes, as with -m 1
+        assert!(rewrite_file(&path, &matcher, &quick, &HashSet::from([2])).unwrap());
+        assert_eq!("Rust:\nsafe, quick, productive.\nfast\n", fs::read_to_string(&path).unwrap());
+        // nothing left to replace, so the file is left alone
+        assert!(!rewrite_file(&path, &matcher, &quick, &HashSet::from([2])).unwrap());
+        assert!(!rewrite_file(&path, &matcher, &quick, &HashSet::new()).unwrap());
+
+        // a file that isn't UTF-8 is refused, not mangled
+        fs::write(&path, b"fast \xff\n").unwrap();
+        let err = rewrite_file(&path, &matcher, &quick, &HashSet::from([1])).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        assert_eq!(b"fast \xff\n", &fs::read(&path).unwrap()[..]);
+        // and so is a binary file
+        fs::write(&path, b"fast\0\n").unwrap();
+        let err = rewrite_file(&path, &matcher, &quick, &HashSet::from([1])).unwrap_err();
+        assert_eq!(ErrorKind::InvalidInput, err.kind());
+
+        // a BOM isn't part of the first line, but it's kept
+        let matcher = Matcher::Regex(Regex::new("^fast$").unwrap());
+        fs::write(&path, "\u{feff}fast\nfast\n").unwrap();
+        assert!(rewrite_file(&path, &matcher, &quick, &HashSet::from([1, 2])).unwrap());
+        assert_eq!("\u{feff}quick\nquick\n", fs::read_to_string(&path).unwrap());
+
+        // the temporary file never reuses a name that's already taken
+        let (first, _) = create_temp(&path).unwrap();
+        let (second, _) = create_temp(&path).unwrap();
+        assert_ne!(first, second);
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+        // and nothing is left behind
+        assert_eq!(1, fs::read_dir(&dir).unwrap().count());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("minigrep-replace-{}.sh", process::id()));
+        fs::write(&path, "echo fast\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o754)).unwrap();
+
+        write_atomically(&path, b"echo quick\n").unwrap();
+        assert_eq!(0o754, fs::metadata(&path).unwrap().permissions().mode() & 0o777);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn writes_through_symlinks() {
+        let dir = std::env::temp_dir().join(format!("minigrep-symlink-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (target, link) = (dir.join("poem.txt"), dir.join("link.txt"));
+        fs::write(&target, "fast\n").unwrap();
+        std::os::unix::fs::symlink("poem.txt", &link).unwrap();
+
+        write_atomically(&link, b"quick\n").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!("quick\n", fs::read_to_string(&target).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
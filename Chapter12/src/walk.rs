@@ -0,0 +1,363 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::glob::{Glob, GlobError};
+use crate::ignore::{Gitignore, Verdict};
+
+// Decides which files a recursive search looks at.
+// Excluded directories are not descended into at all.
+pub struct FileFilter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+    // search files and directories whose names start with `.`
+    hidden: bool,
+    // skip what .gitignore and .ignore files say to
+    ignore_files: bool,
+}
+
+impl FileFilter {
+    pub fn new(include: &[String], exclude: &[String]) -> Result<FileFilter, GlobError> {
+        let compile = |globs: &[String]| globs.iter().map(|g| Glob::new(g)).collect::<Result<Vec<_>, _>>();
+        Ok(FileFilter {
+            include: compile(include)?,
+            exclude: compile(exclude)?,
+            hidden: false,
+            ignore_files: true,
+        })
+    }
+
+    pub fn hidden(mut self, hidden: bool) -> FileFilter {
+        self.hidden = hidden;
+        self
+    }
+
+    pub fn ignore_files(mut self, ignore_files: bool) -> FileFilter {
+        self.ignore_files = ignore_files;
+        self
+    }
+
+    fn is_excluded(&self, relative: &str, name: &str) -> bool {
+        self.exclude.iter().any(|glob| matches(glob, relative, name))
+    }
+
+    // With no include globs every file is included
+    fn is_included(&self, relative: &str, name: &str) -> bool {
+        self.include.is_empty() || self.include.iter().any(|glob| matches(glob, relative, name))
+    }
+}
+
+fn matches(glob: &Glob, relative: &str, name: &str) -> bool {
+    if glob.has_separator() {
+        glob.is_match(relative)
+    } else {
+        glob.is_match(name)
+    }
+}
+
+// An ignore file in effect, and how to turn a path relative to the search
+// root into one relative to the ignore file's directory: drop `below` from
+// the front for ignore files under the root, put `above` in front for those
+// in directories above it
+struct IgnoreFile {
+    above: String,
+    below: String,
+    ignore: Gitignore,
+}
+
+// The ignore files found on the way down to the current directory
+type IgnoreStack = Vec<IgnoreFile>;
+
+// What a walk found: the files, and the directories and ignore files that
+// couldn't be read, which are skipped rather than ending the walk
+#[derive(Debug, Default)]
+pub struct Walk {
+    pub files: Vec<PathBuf>,
+    pub errors: Vec<(PathBuf, io::Error)>,
+}
+
+// Recursively lists the files under root that pass the filter, in sorted order
+// so that output is the same from run to run.
+// Symbolic links are skipped to avoid cycles.
+pub fn walk(root: &Path, filter: &FileFilter) -> Walk {
+    let mut walk = Walk::default();
+    let mut ignores = Vec::new();
+    if filter.ignore_files {
+        push_ancestor_ignore_files(root, &mut ignores, &mut walk.errors);
+    }
+    walk_dir(root, "", filter, &mut ignores, &mut walk);
+    walk
+}
+
+fn walk_dir(dir: &Path, prefix: &str, filter: &FileFilter, ignores: &mut IgnoreStack, walk: &mut Walk) {
+    let entries = match read_sorted(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            walk.errors.push((dir.to_path_buf(), e));
+            return;
+        }
+    };
+    let pushed = if filter.ignore_files { push_ignore_files(dir, prefix, ignores, &mut walk.errors) } else { 0 };
+
+    for (name, path, file_type) in entries {
+        let relative = format!("{prefix}{name}");
+        let is_dir = file_type.is_dir();
+        if file_type.is_symlink()
+            || (!filter.hidden && name.starts_with('.'))
+            // the repository itself is never worth searching, even with --hidden
+            || (filter.ignore_files && is_dir && name == ".git")
+            || is_ignored(ignores, &relative, is_dir)
+            || filter.is_excluded(&relative, &name)
+        {
+            continue;
+        }
+        if is_dir {
+            walk_dir(&path, &format!("{relative}/"), filter, ignores, walk);
+        } else if filter.is_included(&relative, &name) {
+            walk.files.push(path);
+        }
+    }
+
+    ignores.truncate(ignores.len() - pushed);
+}
+
+// The name, path and type of everything in dir, sorted by name
+fn read_sorted(dir: &Path) -> io::Result<Vec<(String, PathBuf, fs::FileType)>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        entries.push((name, entry.path(), entry.file_type()?));
+    }
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(entries)
+}
+
+// Loads dir's .gitignore and then its .ignore, so the .ignore rules take
+// precedence. Returns how many were pushed.
+fn push_ignore_files(dir: &Path, prefix: &str, ignores: &mut IgnoreStack, errors: &mut Vec<(PathBuf, io::Error)>) -> usize {
+    let mut pushed = 0;
+    for file in [".gitignore", ".ignore"] {
+        let path = dir.join(file);
+        match Gitignore::load(&path) {
+            Ok(ignore) if ignore.is_empty() => {}
+            Ok(ignore) => {
+                ignores.push(IgnoreFile {
+                    above: String::new(),
+                    below: prefix.to_string(),
+                    ignore,
+                });
+                pushed += 1;
+            }
+            Err(e) => errors.push((path, e)),
+        }
+    }
+    pushed
+}
+
+// Searching part of a repository still follows the ignore files above it,
+// e.g. `minigrep x src` skips src/target when the top .gitignore has
+// `target/`. They're loaded from the repository's top directory, the one
+// holding .git, down to root's parent. Outside a repository there are none.
+fn push_ancestor_ignore_files(root: &Path, ignores: &mut IgnoreStack, errors: &mut Vec<(PathBuf, io::Error)>) {
+    let Ok(root) = fs::canonicalize(root) else { return };
+    let Some(top) = root.ancestors().find(|dir| dir.join(".git").exists()) else { return };
+    let mut dirs: Vec<&Path> = root.ancestors().skip(1).take_while(|dir| dir.starts_with(top)).collect();
+    dirs.reverse();
+    for dir in dirs {
+        let above: String = root
+            .strip_prefix(dir)
+            .unwrap_or(&root)
+            .components()
+            .map(|c| format!("{}/", c.as_os_str().to_string_lossy()))
+            .collect();
+        let start = ignores.len();
+        push_ignore_files(dir, "", ignores, errors);
+        for file in &mut ignores[start..] {
+            file.above = above.clone();
+        }
+    }
+}
+
+// The ignore files closest to the path get the first say
+fn is_ignored(ignores: &IgnoreStack, relative: &str, is_dir: bool) -> bool {
+    ignores
+        .iter()
+        .rev()
+        .find_map(|file| {
+            let below = &relative[file.below.len()..];
+            if file.above.is_empty() {
+                file.ignore.matched(below, is_dir)
+            } else {
+                file.ignore.matched(&format!("{}{below}", file.above), is_dir)
+            }
+        })
+        == Some(Verdict::Ignored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds a small source tree under the system temp directory
+    fn fixture(name: &str) -> PathBuf {
+        let files = ["Cargo.toml", "src/lib.rs", "src/main.rs", "src/notes.md", "target/debug/out.rs"];
+        tree(name, &files.map(|file| (file, "fn main() {}\n")))
+    }
+
+    // Builds a tree from (path, contents) pairs
+    fn tree(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("minigrep-walk-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for (file, contents) in files {
+            let path = root.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+        root
+    }
+
+    // A repository with ignore files at a couple of levels
+    fn repository(name: &str) -> PathBuf {
+        tree(name, &[
+            (".git/HEAD", "ref: refs/heads/main\n"),
+            (".gitignore", "target/\n*.log\n!keep.log\n/notes.md\n"),
+            (".env", "SECRET=1\n"),
+            ("notes.md", ""),
+            ("debug.log", ""),
+            ("keep.log", ""),
+            ("src/lib.rs", ""),
+            ("src/notes.md", ""),
+            ("src/generated/.gitignore", "*\n!.gitignore\n!api.rs\n"),
+            ("src/generated/api.rs", ""),
+            ("src/generated/cache.rs", ""),
+            ("src/.hidden/secret.rs", ""),
+            ("docs/.ignore", "!*.log\ndraft.md\n"),
+            ("docs/draft.md", ""),
+            ("docs/build.log", ""),
+            ("target/debug/out.rs", ""),
+        ])
+    }
+
+    fn relative(root: &Path, files: Vec<PathBuf>) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| f.strip_prefix(root).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect()
+    }
+
+    #[test]
+    fn walks_everything_sorted() {
+        let root = fixture("all");
+        let filter = FileFilter::new(&[], &[]).unwrap();
+        assert_eq!(
+            vec!["Cargo.toml", "src/lib.rs", "src/main.rs", "src/notes.md", "target/debug/out.rs"],
+            relative(&root, walk(&root, &filter).files),
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let root = fixture("globs");
+        let filter = FileFilter::new(&["*.rs".to_string()], &["target".to_string(), "main.rs".to_string()]).unwrap();
+        assert_eq!(vec!["src/lib.rs"], relative(&root, walk(&root, &filter).files));
+
+        let filter = FileFilter::new(&["src/*.{md,toml}".to_string()], &[]).unwrap();
+        assert_eq!(vec!["src/notes.md"], relative(&root, walk(&root, &filter).files));
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn respects_ignore_files() {
+        let root = repository("ignore");
+        let filter = FileFilter::new(&[], &[]).unwrap();
+        assert_eq!(
+            // .ignore in docs re-includes build.log, the generated dir only lets api.rs through
+            vec!["docs/build.log", "keep.log", "src/generated/api.rs", "src/lib.rs", "src/notes.md"],
+            relative(&root, walk(&root, &filter).files),
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn hidden_and_no_ignore() {
+        let root = repository("overrides");
+        let filter = FileFilter::new(&[], &[]).unwrap().hidden(true);
+        assert_eq!(
+            vec![
+                ".env",
+                ".gitignore",
+                "docs/.ignore",
+                "docs/build.log",
+                "keep.log",
+                "src/.hidden/secret.rs",
+                "src/generated/.gitignore",
+                "src/generated/api.rs",
+                "src/lib.rs",
+                "src/notes.md",
+            ],
+            relative(&root, walk(&root, &filter).files),
+        );
+
+        let filter = FileFilter::new(&["*.rs".to_string()], &[]).unwrap().ignore_files(false);
+        assert_eq!(
+            vec!["src/generated/api.rs", "src/generated/cache.rs", "src/lib.rs", "target/debug/out.rs"],
+            relative(&root, walk(&root, &filter).files),
+        );
+
+        // with both, even .git is searched
+        let filter = FileFilter::new(&[], &["*.md".to_string(), "*.rs".to_string(), "*.log".to_string()])
+            .unwrap()
+            .hidden(true)
+            .ignore_files(false);
+        assert_eq!(
+            vec![".env", ".git/HEAD", ".gitignore", "docs/.ignore", "src/generated/.gitignore"],
+            relative(&root, walk(&root, &filter).files),
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn ignore_files_above_the_root() {
+        let root = repository("above");
+        fs::create_dir_all(root.join("src/target")).unwrap();
+        fs::write(root.join("src/target/x.rs"), "").unwrap();
+        fs::write(root.join("src/app.log"), "").unwrap();
+        let filter = FileFilter::new(&[], &[]).unwrap();
+        // the top .gitignore's target/ and *.log, and src/generated's own rules, all
+        // apply. Its /notes.md is anchored to the top, so src/notes.md isn't ignored.
+        assert_eq!(
+            vec!["generated/api.rs", "lib.rs", "notes.md"],
+            relative(&root.join("src"), walk(&root.join("src"), &filter).files),
+        );
+        assert_eq!(vec!["api.rs"], relative(&root.join("src/generated"), walk(&root.join("src/generated"), &filter).files));
+        let filter = filter.ignore_files(false);
+        assert_eq!(6, walk(&root.join("src"), &filter).files.len());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn unreadable_directories_are_skipped() {
+        let root = fixture("unreadable");
+        let filter = FileFilter::new(&[], &[]).unwrap();
+        let walked = walk(&root.join("Cargo.toml"), &filter);
+        assert!(walked.files.is_empty());
+        assert_eq!(root.join("Cargo.toml"), walked.errors[0].0);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let src = root.join("src");
+            fs::set_permissions(&src, fs::Permissions::from_mode(0o000)).unwrap();
+            let walked = walk(&root, &filter);
+            fs::set_permissions(&src, fs::Permissions::from_mode(0o755)).unwrap();
+            // root can read it anyway
+            if !walked.errors.is_empty() {
+                assert_eq!(vec![src.clone()], walked.errors.into_iter().map(|(path, _)| path).collect::<Vec<_>>());
+                assert_eq!(vec!["Cargo.toml", "target/debug/out.rs"], relative(&root, walked.files));
+            }
+        }
+        fs::remove_dir_all(root).unwrap();
+    }
+}
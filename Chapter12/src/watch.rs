@@ -0,0 +1,439 @@
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::io::{self, BufRead, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use crate::encoding::{self, Bom};
+use crate::error::MinigrepError;
+use crate::matcher::Matcher;
+use crate::output::{self, PrintOptions, Sink, Stats};
+use crate::{Config, FileOutput, Input, collect_inputs, inflate, is_binary, print_options, search_lines};
+
+// --watch: like `tail -f file | grep pattern`. The files are searched once as
+// usual, then polled for what has been appended to them and only the matches
+// in the new lines are printed. Directories are walked again on every poll,
+// so files created in them are picked up too.
+// Compressed and UTF-16 files can't be followed, appending to them doesn't
+// simply add lines. Named on the command line they're an error, found by
+// walking a directory they're skipped with a warning.
+// Runs until it's killed, or until stdout goes away.
+pub fn watch(config: &Config, interval: Duration) -> Result<(), MinigrepError> {
+    let matcher = Matcher::new(config)?;
+    let mut watcher = Watcher::new(config, &matcher);
+    loop {
+        watcher.poll(&mut io::stdout().lock())?;
+        thread::sleep(interval);
+    }
+}
+
+// Remembers how far into each file it has searched
+pub struct Watcher<'c> {
+    config: &'c Config,
+    matcher: &'c Matcher,
+    files: HashMap<PathBuf, Position>,
+    polled: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Position {
+    // everything before this has been read
+    offset: u64,
+    // the number of whole lines before offset
+    lines: usize,
+    // the line at the end that's still being written, read but not searched yet
+    partial: Vec<u8>,
+    // a UTF-8 byte order mark at the start isn't searched or counted
+    bom: u64,
+    // walked files that turned out to be binary aren't searched at all
+    skipped: bool,
+    // which file this is, see file_id
+    id: Option<(u64, u64)>,
+}
+
+impl<'c> Watcher<'c> {
+    pub fn new(config: &'c Config, matcher: &'c Matcher) -> Watcher<'c> {
+        Watcher {
+            config,
+            matcher,
+            files: HashMap::new(),
+            polled: false,
+        }
+    }
+
+    // Searches whatever has been added to the files since the last poll, all
+    // of them the first time. Only whole lines are searched, a line that's
+    // still being written waits for the next poll.
+    pub fn poll(&mut self, out: &mut dyn Write) -> Result<Stats, MinigrepError> {
+        let (inputs, unreadable) = collect_inputs(self.config)?;
+        // reported once, not on every poll
+        if !self.polled {
+            for e in unreadable {
+                eprintln!("minigrep: {e}");
+            }
+        }
+        let options = print_options(self.config, &inputs);
+        let mut out = FileOutput::new(out, None);
+        let mut totals = Stats::default();
+        let mut seen = HashMap::new();
+        for input in &inputs {
+            let mut position = self.files.remove(&input.path).unwrap_or_default();
+            match self.search_new(input, &mut position, &options, &mut out) {
+                Ok(stats) => totals.add(&stats),
+                // a file that's gone, e.g. while a log is being rotated, may come
+                // back later, and one that isn't there yet may still turn up
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    if !self.polled {
+                        eprintln!("minigrep: {}: not found, waiting for it", input.path.display());
+                    }
+                    continue;
+                }
+                Err(e) if out.broken => return Err(MinigrepError::Output(e)),
+                Err(e) => return Err(MinigrepError::io(&input.path, e)),
+            }
+            seen.insert(input.path.clone(), position);
+        }
+        out.flush().map_err(MinigrepError::Output)?;
+        // files that disappeared start from the beginning if they come back
+        self.files = seen;
+        self.polled = true;
+        Ok(totals)
+    }
+
+    // Searches the whole lines added to the file since position and moves
+    // position past everything read, moving it back to the start first if
+    // the file has been truncated or replaced by another one
+    fn search_new(
+        &self,
+        input: &Input,
+        position: &mut Position,
+        options: &PrintOptions,
+        out: &mut dyn Write,
+    ) -> io::Result<Stats> {
+        let mut file = File::open(&input.path)?;
+        let metadata = file.metadata()?;
+        let len = metadata.len();
+        let id = file_id(&metadata);
+        if len < position.offset || id != position.id {
+            *position = Position::default();
+            position.id = id;
+        }
+        if len == position.offset || position.skipped {
+            return Ok(Stats::default());
+        }
+        if position.offset == 0 && !self.check_start(input, &mut file, position)? {
+            return Ok(Stats::default());
+        }
+        file.seek(SeekFrom::Start(position.offset))?;
+
+        let mut sink = output::new_sink(self.config.output, options.clone(), self.matcher, out);
+        let partial = std::mem::take(&mut position.partial);
+        let mut shifted = Shifted {
+            sink: sink.as_mut(),
+            lines: position.lines,
+            bytes: (position.offset - position.bom) as usize - partial.len(),
+        };
+        let mut reader = WholeLines::new(partial, file.take(len - position.offset));
+        let label = input.path.display().to_string();
+        let stats = search_lines(self.config, self.matcher, &label, &mut reader, &mut shifted)?;
+        // whatever the search stopped short of, e.g. after -m hits, is skipped
+        while !reader.fill_buf()?.is_empty() {
+            let n = reader.fill_buf()?.len();
+            reader.consume(n);
+        }
+        position.offset += reader.read;
+        position.lines += reader.lines;
+        position.partial = reader.rest();
+        Ok(stats)
+    }
+
+    // The first look at a file: whether it can be searched. A UTF-8 byte
+    // order mark is stepped over.
+    fn check_start(&self, input: &Input, file: &mut File, position: &mut Position) -> io::Result<bool> {
+        let mut head = Vec::new();
+        (&mut *file).take(8192).read_to_end(&mut head)?;
+        let bom = encoding::detect(&head);
+        let kind = if inflate::detect(&head).is_some() {
+            Some("compressed")
+        } else if matches!(bom, Some(Bom::Utf16Le) | Some(Bom::Utf16Be)) {
+            Some("UTF-16")
+        } else {
+            None
+        };
+        if let Some(kind) = kind {
+            let message = format!("--watch can't follow {kind} files");
+            if !input.walked {
+                return Err(io::Error::new(ErrorKind::InvalidInput, message));
+            }
+            eprintln!("minigrep: {}: {message}, skipped", input.path.display());
+            position.skipped = true;
+            return Ok(false);
+        }
+        if input.walked && is_binary(&head) {
+            position.skipped = true;
+            return Ok(false);
+        }
+        if bom == Some(Bom::Utf8) {
+            position.bom = Bom::Utf8.size() as u64;
+            position.offset = position.bom;
+        }
+        Ok(true)
+    }
+}
+
+// The device and inode of the file. logrotate's default is to rename the log
+// and create a new one under the old name, which only shows as a new inode.
+#[cfg(unix)]
+fn file_id(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+// Elsewhere only a file that shrinks is noticed as a new one
+#[cfg(not(unix))]
+fn file_id(_: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+// How much of a file is read at a time
+const CHUNK: usize = 64 * 1024;
+
+// Reads the inner reader a CHUNK at a time, but only hands out whole lines.
+// What's left after the last newline is kept for the next poll, which starts
+// with it.
+struct WholeLines<R> {
+    inner: R,
+    buf: Vec<u8>,
+    // start of what hasn't been handed out
+    pos: usize,
+    // end of the last whole line in buf
+    ready: usize,
+    // bytes read from inner, and the newlines in them
+    read: u64,
+    lines: usize,
+}
+
+impl<R: Read> WholeLines<R> {
+    fn new(partial: Vec<u8>, inner: R) -> WholeLines<R> {
+        WholeLines {
+            inner,
+            buf: partial,
+            pos: 0,
+            ready: 0,
+            read: 0,
+            lines: 0,
+        }
+    }
+
+    // The unfinished line at the end
+    fn rest(mut self) -> Vec<u8> {
+        self.buf.drain(..self.pos);
+        self.buf
+    }
+}
+
+impl<R: Read> Read for WholeLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.fill_buf()?.read(buf)?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+impl<R: Read> BufRead for WholeLines<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.pos == self.ready {
+            // the unfinished line moves to the front, the next chunk goes after it
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+            self.ready = 0;
+            let old = self.buf.len();
+            self.buf.resize(old + CHUNK, 0);
+            let n = self.inner.read(&mut self.buf[old..])?;
+            self.buf.truncate(old + n);
+            if n == 0 {
+                break;
+            }
+            self.read += n as u64;
+            let new = &self.buf[old..];
+            self.lines += new.iter().filter(|&&b| b == b'\n').count();
+            if let Some(i) = new.iter().rposition(|&b| b == b'\n') {
+                self.ready = old + i + 1;
+            }
+        }
+        Ok(&self.buf[self.pos..self.ready])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.pos += n;
+    }
+}
+
+// Passes lines on numbered as if the search had started at the top of the file
+struct Shifted<'s> {
+    sink: &'s mut dyn Sink,
+    lines: usize,
+    bytes: usize,
+}
+
+impl Sink for Shifted<'_> {
+    fn begin_file(&mut self, path: &str) -> io::Result<()> {
+        self.sink.begin_file(path)
+    }
+
+    fn line(&mut self, line_number: usize, byte_offset: usize, line: &str, selected: bool) -> io::Result<()> {
+        self.sink.line(self.lines + line_number, self.bytes + byte_offset, line, selected)
+    }
+
+    fn end_file(&mut self) -> io::Result<()> {
+        self.sink.end_file()
+    }
+
+    fn binary(&mut self) {
+        self.sink.binary()
+    }
+
+    fn wants_more(&self) -> bool {
+        self.sink.wants_more()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::path::Path;
+    use std::process;
+
+    fn append(path: &Path, text: &str) {
+        OpenOptions::new().append(true).open(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    fn poll(watcher: &mut Watcher) -> String {
+        let mut out = Vec::new();
+        watcher.poll(&mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn prints_only_appended_matches() {
+        let path = std::env::temp_dir().join(format!("minigrep-watch-{}.log", process::id()));
+        fs::write(&path, "INFO start\nERROR disk full\n").unwrap();
+        let args = ["minigrep", "--watch", "-n", "ERROR", path.to_str().unwrap()];
+        let config = Config::from_args(args.iter().map(|a| a.to_string()), |_| None).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let mut watcher = Watcher::new(&config, &matcher);
+
+        assert_eq!("2:ERROR disk full\n", poll(&mut watcher));
+        assert_eq!("", poll(&mut watcher));
+        // the last line isn't finished yet
+        append(&path, "INFO retry\nERROR disk");
+        assert_eq!("", poll(&mut watcher));
+        append(&path, " still full\nERROR gave up\n");
+        assert_eq!("4:ERROR disk still full\n5:ERROR gave up\n", poll(&mut watcher));
+
+        // truncated, like logrotate's copytruncate does
+        fs::write(&path, "ERROR after rotation\n").unwrap();
+        assert_eq!("1:ERROR after rotation\n", poll(&mut watcher));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follows_a_rotated_file() {
+        let dir = std::env::temp_dir().join(format!("minigrep-watch-rotate-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        let args = ["minigrep", "--watch", "-n", "ERROR", path.to_str().unwrap()];
+        let config = Config::from_args(args.iter().map(|a| a.to_string()), |_| None).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let mut watcher = Watcher::new(&config, &matcher);
+
+        // not there yet, so it's waited for
+        assert_eq!("", poll(&mut watcher));
+        fs::write(&path, "ERROR one\n").unwrap();
+        assert_eq!("1:ERROR one\n", poll(&mut watcher));
+
+        // renamed and created again, already longer than the old one by the next poll
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "INFO start\nERROR two\nERROR three\n").unwrap();
+        assert_eq!("2:ERROR two\n3:ERROR three\n", poll(&mut watcher));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn picks_up_new_files() {
+        let dir = std::env::temp_dir().join(format!("minigrep-watch-dir-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.log"), "ERROR one\n").unwrap();
+        let args = ["minigrep", "--watch=0.1", "ERROR", dir.to_str().unwrap()];
+        let config = Config::from_args(args.iter().map(|a| a.to_string()), |_| None).unwrap();
+        assert_eq!(Some(Duration::from_millis(100)), config.watch);
+        let matcher = Matcher::new(&config).unwrap();
+        let mut watcher = Watcher::new(&config, &matcher);
+
+        let a = dir.join("a.log").display().to_string();
+        assert_eq!(format!("{a}:ERROR one\n"), poll(&mut watcher));
+        fs::write(dir.join("b.log"), "ERROR two\nINFO fine\n").unwrap();
+        let b = dir.join("b.log").display().to_string();
+        assert_eq!(format!("{b}:ERROR two\n"), poll(&mut watcher));
+        // a file that's removed is simply no longer watched
+        fs::remove_file(dir.join("a.log")).unwrap();
+        assert_eq!("", poll(&mut watcher));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn reads_a_chunk_at_a_time() {
+        let path = std::env::temp_dir().join(format!("minigrep-watch-big-{}.log", process::id()));
+        // lines that cross the end of a chunk, and a last one that isn't finished
+        let line = format!("ERROR {}\n", "x".repeat(1000));
+        let lines = 3 * CHUNK / line.len();
+        fs::write(&path, format!("{}ERROR half", line.repeat(lines))).unwrap();
+        let args = ["minigrep", "--watch", "-b", "ERROR", path.to_str().unwrap()];
+        let config = Config::from_args(args.iter().map(|a| a.to_string()), |_| None).unwrap();
+        let matcher = Matcher::new(&config).unwrap();
+        let mut watcher = Watcher::new(&config, &matcher);
+
+        let mut out = Vec::new();
+        assert_eq!(lines as u64, watcher.poll(&mut out).unwrap().matched_lines);
+        assert_eq!(lines, out.iter().filter(|&&b| b == b'\n').count());
+        // the unfinished line was carried over, not read again
+        let position = &watcher.files[&path];
+        assert_eq!(b"ERROR half", &position.partial[..]);
+        assert_eq!(fs::metadata(&path).unwrap().len(), position.offset);
+        append(&path, " done\n");
+        assert_eq!(format!("{}:ERROR half done\n", lines * line.len()), poll(&mut watcher));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_what_it_cant_follow() {
+        let dir = std::env::temp_dir().join(format!("minigrep-watch-encoded-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy("poem.txt.gz", dir.join("poem.txt.gz")).unwrap();
+        let utf16: Vec<u8> = [0xfeff_u16].into_iter().chain("ERROR wide\n".encode_utf16()).flat_map(u16::to_le_bytes).collect();
+        fs::write(dir.join("wide.log"), utf16).unwrap();
+        fs::write(dir.join("app.log"), "\u{feff}ERROR narrow\n").unwrap();
+        let watch = |path: &Path| {
+            let args = ["minigrep", "--watch", "-b", "ERROR", path.to_str().unwrap()];
+            let config = Config::from_args(args.iter().map(|a| a.to_string()), |_| None).unwrap();
+            let matcher = Matcher::new(&config).unwrap();
+            let mut out = Vec::new();
+            Watcher::new(&config, &matcher).poll(&mut out).map(|_| String::from_utf8(out).unwrap())
+        };
+
+        // named, they're an error
+        for name in ["poem.txt.gz", "wide.log"] {
+            match watch(&dir.join(name)) {
+                Err(MinigrepError::Io { source, .. }) => assert_eq!(ErrorKind::InvalidInput, source.kind()),
+                other => panic!("expected {name} to be refused, got {other:?}"),
+            }
+        }
+        // walked, they're skipped. A UTF-8 byte order mark is just left out.
+        let app = dir.join("app.log").display().to_string();
+        assert_eq!(format!("{app}:0:ERROR narrow\n"), watch(&dir).unwrap());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,333 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+pub mod scores;
+pub mod server;
+
+// How a guess compares to the secret number
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    // the guess is smaller than the secret
+    Less,
+    Greater,
+    Equal,
+}
+
+// Harder levels pick from a bigger range and allow fewer guesses for its size,
+// but their scores count for more
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    pub const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    pub fn range(self) -> RangeInclusive<u32> {
+        match self {
+            Difficulty::Easy => 1..=50,
+            Difficulty::Normal => 1..=100,
+            Difficulty::Hard => 1..=1000,
+        }
+    }
+
+    pub fn max_attempts(self) -> u32 {
+        match self {
+            Difficulty::Easy => 10,
+            Difficulty::Normal => 7,
+            Difficulty::Hard => 10,
+        }
+    }
+
+    fn multiplier(self) -> u32 {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal => 2,
+            Difficulty::Hard => 5,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Difficulty> {
+        Difficulty::ALL.into_iter().find(|d| d.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl fmt::Display for Difficulty {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Playing,
+    Won,
+    // all the attempts were used up without finding the number
+    Lost,
+}
+
+// One round of the game: the secret number and the guesses made so far.
+// It doesn't read or print anything, so it can be tested on its own.
+pub struct Game {
+    difficulty: Difficulty,
+    secret_number: u32,
+    guesses: u32,
+    state: State,
+}
+
+impl Game {
+    // The same seed always picks the same secret number, which is what tests need
+    pub fn with_seed(difficulty: Difficulty, seed: u64) -> Game {
+        Game::from_rng(difficulty, &mut StdRng::seed_from_u64(seed))
+    }
+
+    // A secret number nobody can predict, for playing for real
+    pub fn random(difficulty: Difficulty) -> Game {
+        Game::from_rng(difficulty, &mut rand::thread_rng())
+    }
+
+    pub fn from_rng(difficulty: Difficulty, rng: &mut impl Rng) -> Game {
+        Game {
+            difficulty,
+            secret_number: rng.gen_range(difficulty.range()),
+            guesses: 0,
+            state: State::Playing,
+        }
+    }
+
+    // Guesses after the game is over are still compared, but don't count
+    pub fn guess(&mut self, guess: u32) -> Outcome {
+        let outcome = match guess.cmp(&self.secret_number) {
+            Ordering::Less => Outcome::Less,
+            Ordering::Greater => Outcome::Greater,
+            Ordering::Equal => Outcome::Equal,
+        };
+        if self.state == State::Playing {
+            self.guesses += 1;
+            if outcome == Outcome::Equal {
+                self.state = State::Won;
+            } else if self.guesses == self.difficulty.max_attempts() {
+                self.state = State::Lost;
+            }
+        }
+        outcome
+    }
+
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty
+    }
+
+    pub fn guesses(&self) -> u32 {
+        self.guesses
+    }
+
+    pub fn attempts_left(&self) -> u32 {
+        self.difficulty.max_attempts() - self.guesses
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    // Only a won game has a score. Finding the number with the first guess is
+    // worth 1000 points, each extra guess takes an equal share of that away
+    // and each second spent takes 5 more. The level then multiplies it.
+    pub fn score(&self, elapsed: Duration) -> Option<u32> {
+        if self.state != State::Won {
+            return None;
+        }
+        let max = self.difficulty.max_attempts();
+        let base = 1000 * (max - self.guesses + 1) / max;
+        let penalty = u32::try_from(elapsed.as_secs()).unwrap_or(u32::MAX).saturating_mul(5);
+        // winning is always worth something
+        Some(base.saturating_sub(penalty).max(1) * self.difficulty.multiplier())
+    }
+}
+
+// How a session ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ending {
+    Won { guesses: u32, score: u32 },
+    Lost { secret_number: u32 },
+    // the input ran out before the game was over
+    Quit,
+}
+
+// Plays the game over any input and output, stdin and stdout in main and
+// scripted input in tests. Lines that aren't numbers are skipped, like before.
+pub fn play<R: BufRead, W: Write>(game: &mut Game, mut input: R, mut output: W) -> io::Result<Ending> {
+    let range = game.difficulty().range();
+    writeln!(
+        output,
+        "Guess the number between {} and {}! You have {}.",
+        range.start(),
+        range.end(),
+        attempts(game.attempts_left()),
+    )?;
+    let started = Instant::now();
+    loop {
+        writeln!(output, "Please input your guess:")?;
+
+        let mut guess = String::new();
+        if input.read_line(&mut guess)? == 0 {
+            return Ok(Ending::Quit);
+        }
+
+        writeln!(output, "You guessed: {}", guess.trim())?;
+
+        let guess: u32 = match guess.trim().parse() {
+            Ok(num) => num,
+            Err(_) => continue,
+        };
+
+        match game.guess(guess) {
+            Outcome::Less => writeln!(output, "Too Small!")?,
+            Outcome::Greater => writeln!(output, "Too Big")?,
+            Outcome::Equal => {
+                let score = game.score(started.elapsed()).unwrap_or_default();
+                writeln!(output, "You win! Score: {score}")?;
+                return Ok(Ending::Won {
+                    guesses: game.guesses(),
+                    score,
+                });
+            }
+        }
+        if game.state() == State::Lost {
+            writeln!(output, "You lose! The number was {}.", game.secret_number)?;
+            return Ok(Ending::Lost {
+                secret_number: game.secret_number,
+            });
+        }
+        writeln!(output, "{} left.", attempts(game.attempts_left()))?;
+    }
+}
+
+fn attempts(n: u32) -> String {
+    if n == 1 { "1 attempt".to_string() } else { format!("{n} attempts") }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn outcomes() {
+        let mut game = Game::with_seed(Difficulty::Normal, 7);
+        let secret = game.secret_number;
+        assert!(Difficulty::Normal.range().contains(&secret));
+        assert_eq!(Outcome::Less, game.guess(secret - 1));
+        assert_eq!(Outcome::Greater, game.guess(secret + 1));
+        assert_eq!(Outcome::Equal, game.guess(secret));
+        assert_eq!((3, State::Won), (game.guesses(), game.state()));
+
+        // the seed decides the secret
+        assert_eq!(secret, Game::with_seed(Difficulty::Normal, 7).secret_number);
+    }
+
+    #[test]
+    fn difficulty_levels() {
+        for difficulty in Difficulty::ALL {
+            let game = Game::with_seed(difficulty, 3);
+            assert!(difficulty.range().contains(&game.secret_number));
+            assert_eq!(difficulty.max_attempts(), game.attempts_left());
+            assert_eq!(Some(difficulty), Difficulty::parse(difficulty.name()));
+        }
+        assert_eq!(Some(Difficulty::Hard), Difficulty::parse("HARD"));
+        assert_eq!(None, Difficulty::parse("impossible"));
+    }
+
+    #[test]
+    fn losing() {
+        let mut game = Game::with_seed(Difficulty::Easy, 7);
+        let wrong = game.secret_number % 50 + 1;
+        for _ in 0..Difficulty::Easy.max_attempts() {
+            assert_eq!(State::Playing, game.state());
+            game.guess(wrong);
+        }
+        assert_eq!((State::Lost, 0), (game.state(), game.attempts_left()));
+        // too late now
+        game.guess(game.secret_number);
+        assert_eq!(State::Lost, game.state());
+        assert_eq!(None, game.score(Duration::ZERO));
+    }
+
+    #[test]
+    fn scoring() {
+        let won_after = |difficulty: Difficulty, guesses: u32, secs: u64| {
+            let mut game = Game::with_seed(difficulty, 7);
+            for _ in 1..guesses {
+                game.guess(0);
+            }
+            game.guess(game.secret_number);
+            game.score(Duration::from_secs(secs))
+        };
+        assert_eq!(Some(2000), won_after(Difficulty::Normal, 1, 0));
+        // each extra guess costs a seventh of 1000 on normal, each second 5 points
+        assert_eq!(Some(2 * 857), won_after(Difficulty::Normal, 2, 0));
+        assert_eq!(Some(2 * 807), won_after(Difficulty::Normal, 2, 10));
+        assert_eq!(Some(5 * 100), won_after(Difficulty::Hard, 10, 0));
+        assert_eq!(Some(1), won_after(Difficulty::Easy, 10, 3600));
+    }
+
+    #[test]
+    fn replays_a_session() {
+        let mut game = Game::with_seed(Difficulty::Normal, 7);
+        let secret = game.secret_number;
+        let input = format!("{}\nabc\n{}\n{secret}\n", secret - 1, secret + 1);
+        let mut output = Vec::new();
+
+        let ending = play(&mut game, input.as_bytes(), &mut output).unwrap();
+        let Ending::Won { guesses: 3, score } = ending else { panic!("expected a win, got {ending:?}") };
+        let expected = format!(
+            "Guess the number between 1 and 100! You have 7 attempts.\n\
+             Please input your guess:\nYou guessed: {}\nToo Small!\n6 attempts left.\n\
+             Please input your guess:\nYou guessed: abc\n\
+             Please input your guess:\nYou guessed: {}\nToo Big\n5 attempts left.\n\
+             Please input your guess:\nYou guessed: {secret}\nYou win! Score: {score}\n",
+            secret - 1,
+            secret + 1,
+        );
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn replays_a_loss() {
+        let mut game = Game::with_seed(Difficulty::Easy, 7);
+        let secret = game.secret_number;
+        let wrong = secret % 50 + 1;
+        let input = format!("{wrong}\n").repeat(12);
+        let mut output = Vec::new();
+
+        assert_eq!(
+            Ending::Lost { secret_number: secret },
+            play(&mut game, input.as_bytes(), &mut output).unwrap(),
+        );
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.ends_with(&format!("1 attempt left.\nPlease input your guess:\nYou guessed: {wrong}\nToo {}\nYou lose! The number was {secret}.\n", if wrong < secret { "Small!" } else { "Big" })));
+        assert_eq!(10, output.matches("You guessed").count());
+    }
+
+    #[test]
+    fn input_runs_out() {
+        let mut game = Game::with_seed(Difficulty::Normal, 7);
+        let mut output = Vec::new();
+        assert_eq!(Ending::Quit, play(&mut game, "0\n".as_bytes(), &mut output).unwrap());
+        assert_eq!(1, game.guesses());
+    }
+}
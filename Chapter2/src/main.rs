@@ -1,35 +1,109 @@
-use std::{cmp::Ordering, io};
+use std::path::Path;
+use std::{env, io, process};
 
-use rand::Rng;
+use guessing_game::scores::{self, HighScores};
+use guessing_game::server::Server;
+use guessing_game::{play, Difficulty, Ending, Game};
 
 fn main() {
-    println!("Guess the number!");
+    let options = options(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{err}");
+        process::exit(2);
+    });
 
-    let secret_number = rand::thread_rng().gen_range(1..=100);
-    // println!("The secret number is: {secret_number}");
-    loop {
-        println!("Please input your guess:");
+    if let Some(addr) = options.serve {
+        serve(&addr, options.difficulty).unwrap_or_else(|err| {
+            eprintln!("{addr}: {err}");
+            process::exit(1);
+        });
+        return;
+    }
+
+    // a missing score file is fine, one we can't read at all isn't
+    let path = scores::default_path();
+    let (mut table, corrupt) = HighScores::load(&path).unwrap_or_else(|err| {
+        eprintln!("{}: {err}", path.display());
+        process::exit(1);
+    });
+    if let Some(error) = &corrupt {
+        eprintln!("{}: {error}, starting with an empty table", path.display());
+    }
+
+    if options.show_scores {
+        table.leaderboard(io::stdout()).expect("Failed to print the scores");
+        return;
+    }
+
+    let mut game = Game::random(options.difficulty);
+    let mut input = io::stdin().lock();
 
-        let mut guess = String::new();
-        
-        io::stdin()
-            .read_line(&mut guess)
+    // the game itself lives in lib.rs, main only connects it to the terminal
+    let ending = play(&mut game, &mut input, io::stdout()).expect("Failed to read line");
+    if let Ending::Won { guesses, score } = ending {
+        let rank = scores::record(&mut table, options.difficulty, score, guesses, &mut input, io::stdout())
             .expect("Failed to read line");
-    
-        println!("You guessed: {guess}");
+        if rank.is_some() {
+            if let Err(err) = save(&table, &path, corrupt.is_some()) {
+                eprintln!("couldn't save the scores to {}: {err}", path.display());
+            }
+        }
+    }
+}
 
-        let guess: u32 = match guess.trim().parse() {
-            Ok(num) => num,
-            Err(_) => continue,
-        };
+// A corrupt file is only moved aside now that there's a new table to take its place
+fn save(table: &HighScores, path: &Path, corrupt: bool) -> io::Result<()> {
+    if corrupt {
+        let backup = HighScores::set_aside(path)?;
+        eprintln!("the old scores are kept in {}", backup.display());
+    }
+    table.save(path)
+}
+
+// Multiplayer: players connect with e.g. `nc localhost 7878` and take turns
+fn serve(addr: &str, difficulty: Difficulty) -> io::Result<()> {
+    let server = Server::bind(addr, difficulty)?;
+    println!("Playing {difficulty} on {}", server.local_addr()?);
+    server.run()
+}
+
+struct Options {
+    // --difficulty easy, normal (the default) or hard
+    difficulty: Difficulty,
+    // --scores prints the high score table instead of playing
+    show_scores: bool,
+    // --serve ADDRESS runs a multiplayer game instead, e.g. --serve 0.0.0.0:7878
+    serve: Option<String>,
+}
 
-        match guess.cmp(&secret_number) {
-            Ordering::Less => println!("Too Small!"),
-            Ordering::Greater => println!("Too Big"),
-            Ordering::Equal => {
-                println!("You win!");
-                break;
-            },
+fn options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        difficulty: Difficulty::Normal,
+        show_scores: false,
+        serve: None,
+    };
+    while let Some(arg) = args.next() {
+        if arg == "--scores" {
+            options.show_scores = true;
+            continue;
         }
+        if let Some(value) = value(&arg, "--serve", &mut args)? {
+            options.serve = Some(value);
+            continue;
+        }
+        let Some(value) = value(&arg, "--difficulty", &mut args)? else {
+            return Err(format!("unknown argument '{arg}'"));
+        };
+        options.difficulty = Difficulty::parse(&value)
+            .ok_or_else(|| format!("unknown difficulty '{value}', pick easy, normal or hard"))?;
+    }
+    Ok(options)
+}
+
+// The value of `--flag value` or `--flag=value`, None if arg is another flag
+fn value(arg: &str, flag: &str, args: &mut impl Iterator<Item = String>) -> Result<Option<String>, String> {
+    match arg.strip_prefix(flag) {
+        Some("") => args.next().map(Some).ok_or_else(|| format!("{flag} needs a value")),
+        Some(value) if value.starts_with('=') => Ok(Some(value[1..].to_string())),
+        _ => Ok(None),
     }
 }
@@ -0,0 +1,390 @@
+use std::cmp::Reverse;
+use std::env;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::Difficulty;
+
+// The best scores of each difficulty level, kept in a text file between games:
+//   guessing-game-scores 1
+//   normal<TAB>1714<TAB>2<TAB>alice
+//   hard<TAB>500<TAB>10<TAB>bob
+// The first line says which version of the format the rest is in, so the
+// format can change later without old files being misread.
+
+pub const VERSION: u32 = 1;
+const HEADER: &str = "guessing-game-scores";
+// how many scores are kept for each level
+pub const TABLE_SIZE: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub difficulty: Difficulty,
+    pub name: String,
+    pub score: u32,
+    pub guesses: u32,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HighScores {
+    // best first within each level
+    entries: Vec<Entry>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    MissingHeader,
+    UnsupportedVersion(String),
+    // the line number, starting at 1
+    BadLine(usize),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::MissingHeader => write!(f, "not a high score file"),
+            ParseError::UnsupportedVersion(version) => write!(f, "unsupported version '{version}'"),
+            ParseError::BadLine(line) => write!(f, "can't read line {line}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+// Where the scores are kept: GUESSING_GAME_SCORES, or a file in the current directory
+pub fn default_path() -> PathBuf {
+    env::var_os("GUESSING_GAME_SCORES")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(".guessing-game-scores"))
+}
+
+impl HighScores {
+    pub fn parse(contents: &str) -> Result<HighScores, ParseError> {
+        let mut lines = contents.lines();
+        let version = lines
+            .next()
+            .and_then(|header| header.strip_prefix(HEADER))
+            .ok_or(ParseError::MissingHeader)?
+            .trim();
+        if version != VERSION.to_string() {
+            return Err(ParseError::UnsupportedVersion(version.to_string()));
+        }
+        let mut scores = HighScores::default();
+        for (i, line) in lines.enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry = parse_entry(line).ok_or(ParseError::BadLine(i + 2))?;
+            scores.add(entry);
+        }
+        Ok(scores)
+    }
+
+    // A missing file just means nobody has played yet. A corrupt one gives an
+    // empty table along with what's wrong with it, and is left as it is: only
+    // looking at the scores never changes the file. Anything else is a real error.
+    pub fn load(path: &Path) -> io::Result<(HighScores, Option<ParseError>)> {
+        match fs::read(path) {
+            Ok(bytes) => {
+                // a file that isn't text is as corrupt as any other
+                let parsed = String::from_utf8(bytes)
+                    .map_err(|_| ParseError::MissingHeader)
+                    .and_then(|contents| HighScores::parse(&contents));
+                match parsed {
+                    Ok(scores) => Ok((scores, None)),
+                    Err(error) => Ok((HighScores::default(), Some(error))),
+                }
+            }
+            Err(error) => match error.kind() {
+                ErrorKind::NotFound => Ok((HighScores::default(), None)),
+                _ => Err(error),
+            },
+        }
+    }
+
+    // Before a new table is saved in place of a corrupt file, the corrupt one
+    // is moved aside to path.corrupt, so it isn't lost. Returns where it went.
+    pub fn set_aside(path: &Path) -> io::Result<PathBuf> {
+        let backup = with_suffix(path, ".corrupt");
+        fs::rename(path, &backup)?;
+        Ok(backup)
+    }
+
+    // Writes a temporary file and renames it over path, so a crash halfway
+    // through can't leave a truncated table behind
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let (temp, mut file) = create_temp(path)?;
+        let result = file.write_all(self.to_string().as_bytes()).and_then(|_| fs::rename(&temp, path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result
+    }
+
+    // Whether a score would make it into the table of its level
+    pub fn qualifies(&self, difficulty: Difficulty, score: u32, guesses: u32) -> bool {
+        let key = (Reverse(score), guesses);
+        self.top(difficulty).filter(|e| rank_key(e) <= key).count() < TABLE_SIZE
+    }
+
+    // Adds the entry if it's good enough. Returns its place in the table, starting at 1.
+    // Equal scores are ranked by fewer guesses, then by who got there first.
+    pub fn add(&mut self, entry: Entry) -> Option<usize> {
+        let difficulty = entry.difficulty;
+        let rank = 1 + self.top(difficulty).filter(|e| rank_key(e) <= rank_key(&entry)).count();
+
+        // the sort is stable, so of equal entries the older one stays ahead
+        self.entries.push(entry);
+        self.entries.sort_by_key(|e| (e.difficulty, rank_key(e)));
+        // drop whatever fell off the bottom
+        let mut kept = 0;
+        self.entries.retain(|e| {
+            if e.difficulty != difficulty {
+                return true;
+            }
+            kept += 1;
+            kept <= TABLE_SIZE
+        });
+        (rank <= TABLE_SIZE).then_some(rank)
+    }
+
+    pub fn top(&self, difficulty: Difficulty) -> impl Iterator<Item = &Entry> {
+        self.entries.iter().filter(move |e| e.difficulty == difficulty)
+    }
+
+    // What --scores prints
+    pub fn leaderboard(&self, mut output: impl Write) -> io::Result<()> {
+        for difficulty in Difficulty::ALL {
+            writeln!(output, "{difficulty}")?;
+            let mut any = false;
+            for (i, entry) in self.top(difficulty).enumerate() {
+                let guesses = if entry.guesses == 1 { "guess" } else { "guesses" };
+                writeln!(
+                    output,
+                    "{:>4}. {:<20} {:>6}  ({} {guesses})",
+                    i + 1,
+                    entry.name,
+                    entry.score,
+                    entry.guesses,
+                )?;
+                any = true;
+            }
+            if !any {
+                writeln!(output, "      no scores yet")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for HighScores {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{HEADER} {VERSION}")?;
+        for difficulty in Difficulty::ALL {
+            for e in self.top(difficulty) {
+                writeln!(f, "{}\t{}\t{}\t{}", e.difficulty, e.score, e.guesses, e.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A new temporary file next to path, path.<pid>-<n>.tmp. Two games saving at
+// once don't write to the same one, and create_new fails instead of following
+// a file or symlink someone put there, so on a clash the next name is tried.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, fs::File)> {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    loop {
+        let n = NEXT.fetch_add(1, Ordering::Relaxed);
+        let temp = with_suffix(path, &format!(".{}-{n}.tmp", process::id()));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+// path.corrupt, path.<pid>-<n>.tmp: next to path, whatever its extension
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+// Smaller is better: a higher score, then fewer guesses
+fn rank_key(entry: &Entry) -> (Reverse<u32>, u32) {
+    (Reverse(entry.score), entry.guesses)
+}
+
+fn parse_entry(line: &str) -> Option<Entry> {
+    let mut fields = line.splitn(4, '\t');
+    Some(Entry {
+        difficulty: Difficulty::parse(fields.next()?)?,
+        score: fields.next()?.parse().ok()?,
+        guesses: fields.next()?.parse().ok()?,
+        name: fields.next()?.to_string(),
+    })
+}
+
+// After a win: if the score makes the table, asks for a name and adds it.
+// Returns the place it got.
+pub fn record<R: BufRead, W: Write>(
+    scores: &mut HighScores,
+    difficulty: Difficulty,
+    score: u32,
+    guesses: u32,
+    mut input: R,
+    mut output: W,
+) -> io::Result<Option<usize>> {
+    if !scores.qualifies(difficulty, score, guesses) {
+        return Ok(None);
+    }
+    writeln!(output, "A new high score! What's your name?")?;
+    let mut name = String::new();
+    input.read_line(&mut name)?;
+    let rank = scores.add(Entry {
+        difficulty,
+        name: clean_name(&name),
+        score,
+        guesses,
+    });
+    if let Some(rank) = rank {
+        writeln!(output, "You're number {rank} on the {difficulty} table.")?;
+    }
+    Ok(rank)
+}
+
+// Names are a single field of a line in the file, so no tabs or newlines
+fn clean_name(name: &str) -> String {
+    let name: String = name.trim().chars().map(|c| if c.is_control() { ' ' } else { c }).take(20).collect();
+    if name.is_empty() { "anonymous".to_string() } else { name }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(difficulty: Difficulty, name: &str, score: u32, guesses: u32) -> Entry {
+        Entry {
+            difficulty,
+            name: name.to_string(),
+            score,
+            guesses,
+        }
+    }
+
+    #[test]
+    fn ranks_and_round_trips() {
+        let mut scores = HighScores::default();
+        assert_eq!(Some(1), scores.add(entry(Difficulty::Normal, "alice", 1428, 3)));
+        assert_eq!(Some(1), scores.add(entry(Difficulty::Normal, "bob", 2000, 1)));
+        assert_eq!(Some(1), scores.add(entry(Difficulty::Hard, "carol", 500, 10)));
+        // a tie goes to fewer guesses, then to whoever was first
+        assert_eq!(Some(2), scores.add(entry(Difficulty::Normal, "dave", 1428, 2)));
+        assert_eq!(Some(4), scores.add(entry(Difficulty::Normal, "erin", 1428, 3)));
+
+        let names: Vec<&str> = scores.top(Difficulty::Normal).map(|e| e.name.as_str()).collect();
+        assert_eq!(vec!["bob", "dave", "alice", "erin"], names);
+
+        let text = scores.to_string();
+        assert_eq!(
+            "guessing-game-scores 1\n\
+             normal\t2000\t1\tbob\nnormal\t1428\t2\tdave\nnormal\t1428\t3\talice\nnormal\t1428\t3\terin\n\
+             hard\t500\t10\tcarol\n",
+            text,
+        );
+        assert_eq!(Ok(scores), HighScores::parse(&text));
+    }
+
+    #[test]
+    fn keeps_the_best_ten() {
+        let mut scores = HighScores::default();
+        for score in 1..=TABLE_SIZE as u32 {
+            scores.add(entry(Difficulty::Easy, "someone", score * 100, 5));
+        }
+        assert!(!scores.qualifies(Difficulty::Easy, 100, 5));
+        assert!(scores.qualifies(Difficulty::Easy, 100, 4));
+        assert!(scores.qualifies(Difficulty::Easy, 101, 9));
+        assert!(scores.qualifies(Difficulty::Hard, 1, 10));
+        assert_eq!(None, scores.add(entry(Difficulty::Easy, "late", 50, 9)));
+        assert_eq!(Some(10), scores.add(entry(Difficulty::Easy, "just", 150, 9)));
+        assert_eq!(TABLE_SIZE, scores.top(Difficulty::Easy).count());
+        assert_eq!(150, scores.top(Difficulty::Easy).last().unwrap().score);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(Err(ParseError::MissingHeader), HighScores::parse("normal\t10\t1\tbob\n"));
+        assert_eq!(Err(ParseError::MissingHeader), HighScores::parse(""));
+        assert_eq!(
+            Err(ParseError::UnsupportedVersion("2".to_string())),
+            HighScores::parse("guessing-game-scores 2\n"),
+        );
+        assert_eq!(
+            Err(ParseError::BadLine(3)),
+            HighScores::parse("guessing-game-scores 1\nnormal\t10\t1\tbob\nimpossible\t10\t1\tbob\n"),
+        );
+    }
+
+    #[test]
+    fn recovers_from_missing_and_corrupt_files() {
+        let dir = env::temp_dir().join(format!("guessing-game-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scores");
+
+        // nobody has played yet
+        assert_eq!((HighScores::default(), None), HighScores::load(&path).unwrap());
+
+        let mut scores = HighScores::default();
+        scores.add(entry(Difficulty::Hard, "carol", 500, 10));
+        scores.save(&path).unwrap();
+        assert_eq!((scores, None), HighScores::load(&path).unwrap());
+
+        fs::write(&path, "guessing-game-scores 1\nhard\tlots\n").unwrap();
+        let (scores, error) = HighScores::load(&path).unwrap();
+        assert_eq!((HighScores::default(), Some(ParseError::BadLine(2))), (scores, error));
+        // loading doesn't touch the file
+        assert_eq!("guessing-game-scores 1\nhard\tlots\n", fs::read_to_string(&path).unwrap());
+        // setting it aside keeps it for a human to look at
+        assert_eq!(with_suffix(&path, ".corrupt"), HighScores::set_aside(&path).unwrap());
+        assert!(!path.exists());
+        assert_eq!("guessing-game-scores 1\nhard\tlots\n", fs::read_to_string(with_suffix(&path, ".corrupt")).unwrap());
+
+        // the temporary file never reuses a name that's already taken
+        let (first, _) = create_temp(&path).unwrap();
+        let (second, _) = create_temp(&path).unwrap();
+        assert_ne!(first, second);
+        fs::remove_file(first).unwrap();
+        fs::remove_file(second).unwrap();
+        // and saving leaves none behind
+        HighScores::default().save(&path).unwrap();
+        assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn records_a_win() {
+        let mut scores = HighScores::default();
+        let mut output = Vec::new();
+        let rank = record(&mut scores, Difficulty::Normal, 1428, 3, "\tal\nice\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(Some(1), rank);
+        assert_eq!(
+            "A new high score! What's your name?\nYou're number 1 on the normal table.\n",
+            String::from_utf8(output).unwrap(),
+        );
+        assert_eq!("al", scores.top(Difficulty::Normal).next().unwrap().name);
+
+        let mut output = Vec::new();
+        scores.leaderboard(&mut output).unwrap();
+        assert_eq!(
+            "easy\n      no scores yet\nnormal\n   1. al                     1428  (3 guesses)\nhard\n      no scores yet\n",
+            String::from_utf8(output).unwrap(),
+        );
+    }
+}
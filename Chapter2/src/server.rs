@@ -0,0 +1,281 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{attempts, Difficulty, Game, Outcome, State};
+
+// Several players guessing the same number over TCP, one after the other,
+// all of them seeing every guess. It talks plain lines of text, so `nc` or
+// `telnet` is all a player needs.
+//
+// Like multiple producers in Chapter16: every connection gets a thread that
+// reads what its player types and sends it down a channel. A single thread
+// receives from it and owns the game, so the game needs no locking.
+// What the game says to a player goes the other way, down a channel of its
+// own to a thread that writes it to them, so a player who stops reading can't
+// hold up the game for everyone else.
+
+enum Event {
+    Joined { id: usize, name: String, stream: TcpStream },
+    Line { id: usize, line: String },
+    // sent for every connection when it closes, named or not
+    Left { id: usize },
+}
+
+pub struct Server {
+    listener: TcpListener,
+    difficulty: Difficulty,
+}
+
+impl Server {
+    // Port 0 picks a free port, local_addr says which
+    pub fn bind(addr: impl ToSocketAddrs, difficulty: Difficulty) -> io::Result<Server> {
+        Ok(Server {
+            listener: TcpListener::bind(addr)?,
+            difficulty,
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // Accepts players for as long as the server runs
+    pub fn run(self) -> io::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let table = Table::new(self.difficulty);
+        thread::spawn(move || table.run(rx));
+
+        for (id, stream) in self.listener.incoming().enumerate() {
+            // e.g. out of file descriptors, or a client that gave up before
+            // it was accepted; the players already in can keep playing
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    eprintln!("couldn't accept a player: {err}");
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+            };
+            let tx = tx.clone();
+            thread::spawn(move || {
+                // an error only means the player has gone
+                let _ = connection(id, stream, &tx);
+                let _ = tx.send(Event::Left { id });
+            });
+        }
+        Ok(())
+    }
+}
+
+// How long someone who has connected gets to say their name. Once they've
+// joined they can take as long as they like.
+const NAME_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn connection(id: usize, mut stream: TcpStream, tx: &Sender<Event>) -> io::Result<()> {
+    // a connection that never sends a name would hold on to its thread forever
+    stream.set_read_timeout(Some(NAME_TIMEOUT))?;
+    writeln!(stream, "Welcome to the guessing game! What's your name?")?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut name = String::new();
+    if reader.read_line(&mut name)? == 0 {
+        return Ok(());
+    }
+    stream.set_read_timeout(None)?;
+    let name = match name.trim() {
+        "" => format!("player {}", id + 1),
+        name => name.to_string(),
+    };
+    // the game thread only stops if it panicked, then there's nothing to play
+    if tx.send(Event::Joined { id, name, stream }).is_err() {
+        return Ok(());
+    }
+    for line in reader.lines() {
+        if tx.send(Event::Line { id, line: line? }).is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// How many messages can wait for a player before they're dropped for not reading
+const BACKLOG: usize = 256;
+
+struct Player {
+    id: usize,
+    name: String,
+    // to the thread that writes to the player
+    messages: SyncSender<String>,
+    // to hang up on a player who can't keep up
+    stream: TcpStream,
+}
+
+impl Player {
+    fn new(id: usize, name: String, stream: TcpStream) -> Player {
+        let (messages, rx) = mpsc::sync_channel::<String>(BACKLOG);
+        let writer = stream.try_clone();
+        thread::spawn(move || {
+            let Ok(mut writer) = writer else { return };
+            for message in rx {
+                if writeln!(writer, "{message}").is_err() {
+                    break;
+                }
+            }
+        });
+        Player { id, name, messages, stream }
+    }
+}
+
+// The game everyone is playing, with the players in the order they take turns
+struct Table {
+    difficulty: Difficulty,
+    game: Game,
+    started: Instant,
+    players: Vec<Player>,
+    // index into players of whoever guesses next
+    turn: usize,
+}
+
+impl Table {
+    fn new(difficulty: Difficulty) -> Table {
+        Table {
+            difficulty,
+            game: Game::random(difficulty),
+            started: Instant::now(),
+            players: Vec::new(),
+            turn: 0,
+        }
+    }
+
+    fn run(mut self, rx: Receiver<Event>) {
+        for event in rx {
+            match event {
+                Event::Joined { id, name, stream } => self.joined(Player::new(id, name, stream)),
+                Event::Line { id, line } => self.line(id, &line),
+                Event::Left { id } => self.left(id),
+            }
+        }
+    }
+
+    fn joined(&mut self, player: Player) {
+        // the clock only runs while somebody is playing
+        if self.players.is_empty() {
+            self.started = Instant::now();
+        }
+        self.players.push(player);
+        let names: Vec<&str> = self.players.iter().map(|p| p.name.as_str()).collect();
+        let joined = self.players.len() - 1;
+        self.broadcast(&format!("{} joined. Players: {}", self.players[joined].name, names.join(", ")));
+        let range = self.difficulty.range();
+        self.tell(
+            joined,
+            &format!(
+                "Guess the number between {} and {}! {} left.",
+                range.start(),
+                range.end(),
+                attempts(self.game.attempts_left()),
+            ),
+        );
+        if joined == self.turn {
+            self.prompt();
+        } else {
+            self.tell(joined, &format!("It's {}'s turn.", self.players[self.turn].name));
+        }
+    }
+
+    fn line(&mut self, id: usize, line: &str) {
+        let Some(index) = self.players.iter().position(|p| p.id == id) else { return };
+        if index != self.turn {
+            self.tell(index, &format!("Wait for your turn, it's {}'s.", self.players[self.turn].name));
+            return;
+        }
+        let guess: u32 = match line.trim().parse() {
+            Ok(num) => num,
+            Err(_) => {
+                self.tell(index, "Please input a number.");
+                return;
+            }
+        };
+
+        let result = match self.game.guess(guess) {
+            Outcome::Less => "Too Small!",
+            Outcome::Greater => "Too Big",
+            Outcome::Equal => "Correct!",
+        };
+        let name = self.players[index].name.clone();
+        self.broadcast(&format!("{name} guessed {guess}: {result}"));
+        self.turn = (self.turn + 1) % self.players.len();
+        match self.game.state() {
+            State::Playing => {
+                self.broadcast(&format!("{} left.", attempts(self.game.attempts_left())));
+                self.prompt();
+            }
+            State::Won => {
+                let score = self.game.score(self.started.elapsed()).unwrap_or_default();
+                self.broadcast(&format!("{name} wins! Score: {score}"));
+                self.new_round();
+            }
+            State::Lost => {
+                self.broadcast(&format!("Nobody found it! The number was {}.", self.game.secret_number));
+                self.new_round();
+            }
+        }
+    }
+
+    fn left(&mut self, id: usize) {
+        let Some(index) = self.players.iter().position(|p| p.id == id) else { return };
+        let player = self.players.remove(index);
+        self.broadcast(&format!("{} has left the game.", player.name));
+        if self.players.is_empty() {
+            self.turn = 0;
+        } else if index < self.turn {
+            self.turn -= 1;
+        } else if index == self.turn {
+            // the next player moved into their place
+            self.turn %= self.players.len();
+            self.prompt();
+        }
+    }
+
+    fn new_round(&mut self) {
+        self.game = Game::random(self.difficulty);
+        self.started = Instant::now();
+        let range = self.difficulty.range();
+        self.broadcast(&format!(
+            "New round! Guess the number between {} and {}! {} left.",
+            range.start(),
+            range.end(),
+            attempts(self.game.attempts_left()),
+        ));
+        self.prompt();
+    }
+
+    // Tells everyone whose turn it is
+    fn prompt(&mut self) {
+        for i in 0..self.players.len() {
+            if i == self.turn {
+                self.tell(i, "Your turn! Please input your guess:");
+            } else {
+                self.tell(i, &format!("It's {}'s turn.", self.players[self.turn].name));
+            }
+        }
+    }
+
+    fn broadcast(&mut self, message: &str) {
+        for i in 0..self.players.len() {
+            self.tell(i, message);
+        }
+    }
+
+    // A player whose connection broke, or who has stopped reading, is hung up
+    // on. They're removed when their reader thread notices, until then what
+    // they miss doesn't matter.
+    fn tell(&mut self, index: usize, message: &str) {
+        let player = &self.players[index];
+        if player.messages.try_send(message.to_string()).is_err() {
+            let _ = player.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use guessing_game::server::Server;
+use guessing_game::Difficulty;
+
+// A player on the other end of a real connection to the server
+struct Client {
+    name: &'static str,
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+}
+
+impl Client {
+    fn join(addr: SocketAddr, name: &'static str) -> Client {
+        let stream = TcpStream::connect(addr).unwrap();
+        // a test that's waiting for the wrong line fails instead of hanging
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut client = Client {
+            name,
+            reader: BufReader::new(stream.try_clone().unwrap()),
+            writer: stream,
+        };
+        client.expect("Welcome to the guessing game!");
+        client.send(name);
+        client
+    }
+
+    fn send(&mut self, line: &str) {
+        writeln!(self.writer, "{line}").unwrap();
+    }
+
+    // Skips lines until one that starts with prefix, and returns it
+    fn expect(&mut self, prefix: &str) -> String {
+        loop {
+            let mut line = String::new();
+            let read = self.reader.read_line(&mut line);
+            assert!(matches!(read, Ok(n) if n > 0), "{} never got '{prefix}'", self.name);
+            if line.starts_with(prefix) {
+                return line.trim_end().to_string();
+            }
+        }
+    }
+}
+
+fn start_server() -> SocketAddr {
+    let server = Server::bind("127.0.0.1:0", Difficulty::Normal).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    addr
+}
+
+#[test]
+fn players_take_turns_and_see_each_others_guesses() {
+    let addr = start_server();
+    let mut alice = Client::join(addr, "alice");
+    alice.expect("Your turn!");
+    let mut bob = Client::join(addr, "bob");
+    assert_eq!("bob joined. Players: alice, bob", alice.expect("bob joined"));
+    assert_eq!("It's alice's turn.", bob.expect("It's"));
+
+    // guessing out of turn doesn't count
+    bob.send("50");
+    assert_eq!("Wait for your turn, it's alice's.", bob.expect("Wait"));
+
+    // a binary search always finds the number in the 7 attempts of normal
+    let mut players = [alice, bob];
+    let (mut low, mut high) = (1, 100);
+    for turn in 0.. {
+        let guess = (low + high) / 2;
+        let (name, other) = (players[turn % 2].name, players[(turn + 1) % 2].name);
+        players[turn % 2].send(&guess.to_string());
+
+        let prefix = format!("{name} guessed {guess}: ");
+        let seen: Vec<String> = players.iter_mut().map(|p| p.expect(&prefix)).collect();
+        assert_eq!(seen[0], seen[1]);
+        match &seen[0][prefix.len()..] {
+            "Too Small!" => low = guess + 1,
+            "Too Big" => high = guess - 1,
+            "Correct!" => {
+                for player in &mut players {
+                    player.expect(&format!("{name} wins! Score: "));
+                    player.expect("New round!");
+                }
+                break;
+            }
+            result => panic!("unexpected result '{result}'"),
+        }
+        // then it's the other player's turn
+        players[(turn + 1) % 2].expect("Your turn!");
+        assert_eq!(format!("It's {other}'s turn."), players[turn % 2].expect("It's"));
+    }
+}
+
+#[test]
+fn the_turn_passes_on_when_a_player_leaves() {
+    let addr = start_server();
+    let mut alice = Client::join(addr, "alice");
+    alice.expect("Your turn!");
+    let mut bob = Client::join(addr, "bob");
+    alice.expect("bob joined");
+    let mut carol = Client::join(addr, "carol");
+    bob.expect("carol joined");
+
+    alice.send("1");
+    bob.expect("Your turn!");
+    alice.expect("It's bob's turn.");
+    carol.expect("It's bob's turn.");
+    drop(bob);
+    assert_eq!("bob has left the game.", carol.expect("bob"));
+    carol.expect("Your turn!");
+    assert_eq!("It's carol's turn.", alice.expect("It's"));
+}
+
+#[test]
+fn a_player_who_stops_reading_is_dropped() {
+    let addr = start_server();
+    let mut alice = Client::join(addr, "alice");
+    alice.expect("Your turn!");
+    let mut sleepy = TcpStream::connect(addr).unwrap();
+    writeln!(sleepy, "sleepy").unwrap();
+    alice.expect("sleepy joined");
+
+    // every guess out of turn gets an answer, which sleepy never reads
+    thread::spawn(move || while writeln!(sleepy, "50").is_ok() {});
+    assert_eq!("sleepy has left the game.", alice.expect("sleepy has"));
+    // and the game goes on without them
+    alice.send("1");
+    alice.expect("alice guessed 1: ");
+}